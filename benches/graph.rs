@@ -0,0 +1,121 @@
+//! Builds a synthetic flow graph the size of the England & Wales LSOA matrix and reports how long
+//! construction and traversal take and how much memory the graph uses.
+//!
+//! Run with `cargo bench --bench graph`. The size can be changed with the `TTWA_BENCH_NODES` and
+//! `TTWA_BENCH_EDGES_PER_NODE` environment variables.
+use std::time::Instant;
+use ttwa_detection::graph::{self, Edge, EdgeDirection, Graph};
+
+/// Number of LSOAs in England and Wales for the 2021 census
+const DEFAULT_NODES: usize = 35672;
+/// Draws about 21 million flows, which merge into about 19 million distinct origin-destination
+/// pairs, in line with the full LSOA origin-destination table
+const DEFAULT_EDGES_PER_NODE: usize = 600;
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Small xorshift generator so the input is the same on every run without extra dependencies
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Each node sends most of its flows to nearby node indices and a few anywhere, mimicking the
+/// local structure of commuting
+fn synthetic_edges(nodes: usize, edges_per_node: usize) -> Vec<Edge> {
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let mut edges = Vec::with_capacity(nodes * edges_per_node);
+    for source in 0..nodes {
+        for k in 0..edges_per_node {
+            let target = if k % 10 == 0 {
+                rng.next() as usize % nodes
+            } else {
+                // Within twice `edges_per_node` either side, wrapping around at both ends
+                let offset = (rng.next() % (4 * edges_per_node) as u64) as i64;
+                (source as i64 + offset - 2 * edges_per_node as i64).rem_euclid(nodes as i64)
+                    as usize
+            };
+            let weight = 1 + (rng.next() % 20) as u32;
+            edges.push(Edge {
+                source,
+                target,
+                weight,
+            });
+        }
+    }
+    edges
+}
+
+fn main() {
+    let nodes = env_or("TTWA_BENCH_NODES", DEFAULT_NODES);
+    let edges_per_node = env_or("TTWA_BENCH_EDGES_PER_NODE", DEFAULT_EDGES_PER_NODE);
+
+    let start = Instant::now();
+    let edges = synthetic_edges(nodes, edges_per_node);
+    println!(
+        "generated {} flows between {} nodes in {:.2?}",
+        edges.len(),
+        nodes,
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    let graph = Graph::from_edges(nodes, edges);
+    println!(
+        "built graph with {} edges in {:.2?}",
+        graph.edge_count(),
+        start.elapsed()
+    );
+
+    let bytes = graph.heap_size();
+    // The previous layout kept each edge in an Rc allocation (two counters and the edge) plus
+    // three pointers to it, from the edge list and the in and out lists of its nodes
+    let rc_bytes = graph.nodes.len() * std::mem::size_of::<graph::Node>()
+        + graph.edge_count()
+            * (2 * std::mem::size_of::<usize>() + std::mem::size_of::<Edge>() + 3 * 8);
+    println!(
+        "graph uses {:.1} MiB ({:.1} bytes per edge); an Rc<Edge> layout would use about {:.1} MiB",
+        bytes as f64 / (1 << 20) as f64,
+        bytes as f64 / graph.edge_count() as f64,
+        rc_bytes as f64 / (1 << 20) as f64
+    );
+
+    let start = Instant::now();
+    let mut total = 0u64;
+    for node in 0..graph.nodes.len() {
+        total += graph
+            .get_edges(node, EdgeDirection::Out)
+            .map(|edge| edge.weight as u64)
+            .sum::<u64>();
+        total += graph
+            .get_edges(node, EdgeDirection::In)
+            .map(|edge| edge.weight as u64)
+            .sum::<u64>();
+    }
+    println!(
+        "summed in and out edges of every node ({}) in {:.2?}",
+        total,
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    let neighbors = (0..graph.nodes.len())
+        .map(|node| graph.get_neighbors(node).count())
+        .sum::<usize>();
+    println!(
+        "visited {} neighbors of every node in {:.2?}",
+        neighbors,
+        start.elapsed()
+    );
+}
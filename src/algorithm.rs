@@ -0,0 +1,324 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::area::{Area, AreaStats};
+use crate::graph::{EdgeDirection, Graph};
+use crate::params::Parameters;
+use crate::partition::Partition;
+use crate::spatial::SpatialAdjacency;
+
+type NodeIndex = usize;
+
+/// A method of partitioning a commuting graph into travel to work areas.
+///
+/// Implementations share the x-equation criteria in [`Parameters`] and the run [`Options`], and
+/// report their result in the same form so that they can be swapped and compared.
+pub trait RegionalisationAlgorithm {
+    /// Short name used to select the algorithm on the command line
+    fn name(&self) -> &'static str;
+
+    fn run(&self, graph: &Graph, params: &Parameters, options: &Options) -> TtwaResult;
+}
+
+/// Optional caps on how long the dissolution loop may run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    pub max_iterations: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+/// Why the dissolution loop stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// Every remaining area meets the x-equation threshold
+    Converged,
+    /// `Limits::max_iterations` areas were dissolved before convergence
+    IterationLimit,
+    /// `Limits::timeout` elapsed before convergence
+    Timeout,
+    /// Only one area is left to dissolve and it does not meet the x-equation threshold. Its nodes
+    /// would have nowhere to go, so it is kept.
+    SingleArea,
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Converged => write!(f, "converged"),
+            StopReason::IterationLimit => write!(f, "iteration limit reached"),
+            StopReason::Timeout => write!(f, "timed out"),
+            StopReason::SingleArea => write!(f, "only one area left"),
+        }
+    }
+}
+
+/// What to do with a node of a dissolved area that has no commuting neighbour in any other area,
+/// such as the nodes of an island that only commute among themselves
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OrphanPolicy {
+    /// Retry the node once the rest of the dissolved area has been placed, since its neighbours
+    /// may have been in the same area. Nodes that still cannot be placed become unallocatable.
+    #[default]
+    Defer,
+    /// As `Defer`, but place nodes that still cannot be placed in the spatially adjacent area with
+    /// the highest tij2, using `Options::spatial_adjacency`
+    Adjacency,
+    /// Make the node unallocatable straight away
+    Singleton,
+}
+
+/// What to do with nodes that have no resident workers or no jobs, whose flows only go one way
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZeroFlowPolicy {
+    /// Start the node in the area of the node it has the heaviest flow with, in either direction,
+    /// rather than on its own. Nodes with no flows to or from a node that has both workers and
+    /// jobs are excluded.
+    #[default]
+    Attach,
+    /// Exclude the node
+    Exclude,
+}
+
+/// Settings that change how the algorithm runs but not what it optimises
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub limits: Limits,
+    /// Recompute every area's statistics from scratch after each iteration and record any
+    /// difference from the incrementally maintained values, or between the areas' node sets and
+    /// the node-to-area mapping
+    pub check_invariants: bool,
+    /// Only used by the v2 engine; the naive engine compares nodes against every area
+    pub orphan_policy: OrphanPolicy,
+    /// Which nodes share a boundary, used by `OrphanPolicy::Adjacency` and `contiguous`
+    pub spatial_adjacency: Option<Arc<SpatialAdjacency>>,
+    /// Only move nodes to spatially adjacent areas, and only attach zero-flow nodes to spatially
+    /// adjacent nodes, so that areas stay contiguous. Needs `spatial_adjacency`; the naive engine
+    /// only applies it to zero-flow nodes.
+    pub contiguous: bool,
+    pub zero_flow_policy: ZeroFlowPolicy,
+    /// Called with the state of the dissolution loop before every `PROGRESS_INTERVAL`th
+    /// iteration. The engines print nothing themselves.
+    pub progress: Option<fn(&Progress)>,
+}
+
+/// How often `Options::progress` is called, in iterations
+pub const PROGRESS_INTERVAL: usize = 1000;
+
+/// The state of the dissolution loop passed to `Options::progress`
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    pub iteration: usize,
+    /// The lowest x-equation score among the areas that can still be dissolved
+    pub worst_score: f64,
+    /// Areas that are neither empty nor unallocatable
+    pub remaining_areas: usize,
+}
+
+/// An area whose incrementally maintained statistics differ from a full recomputation, or whose
+/// node set disagrees with the node-to-area mapping
+#[derive(Clone, Debug)]
+pub struct Drift {
+    pub iteration: usize,
+    pub area: usize,
+    pub expected: AreaStats,
+    pub found: AreaStats,
+    /// Nodes in the area that the mapping puts in another area, or in none
+    pub unmapped_nodes: Vec<NodeIndex>,
+    /// Nodes the mapping puts in the area that are not in it
+    pub missing_nodes: Vec<NodeIndex>,
+}
+
+pub struct TtwaResult {
+    pub partition: Partition,
+    pub stop_reason: StopReason,
+    /// The lowest x-equation score among the final areas
+    pub worst_score: f64,
+    /// Number of areas dissolved
+    pub iterations: usize,
+    /// Statistics drift found by `Options::check_invariants`; always empty otherwise
+    pub drift: Vec<Drift>,
+    /// Nodes with no resident workers or no jobs, sorted by index
+    pub zero_flow_nodes: Vec<NodeIndex>,
+    /// Zero-flow nodes left out of the algorithm under `Options::zero_flow_policy`. Each is in an
+    /// unallocatable area of its own.
+    pub excluded_nodes: Vec<NodeIndex>,
+}
+
+pub(crate) struct InitialAreas {
+    /// One area per node, indexed by node, except that attached zero-flow nodes leave theirs empty
+    pub areas: Vec<Area>,
+    pub node2area: Vec<usize>,
+    pub zero_flow_nodes: Vec<NodeIndex>,
+    pub excluded_nodes: Vec<NodeIndex>,
+}
+
+/// Puts every node in an area of its own, except for nodes with no resident workers or no jobs,
+/// which are handled by `Options::zero_flow_policy`. Excluded nodes get unallocatable areas.
+pub(crate) fn initial_areas(graph: &Graph, options: &Options) -> InitialAreas {
+    let adjacency = options
+        .spatial_adjacency
+        .as_deref()
+        .filter(|_| options.contiguous);
+    let is_zero_flow =
+        |node: NodeIndex| graph.nodes[node].out_degree == 0 || graph.nodes[node].in_degree == 0;
+    let mut areas = Vec::new();
+    let mut node2area = Vec::with_capacity(graph.nodes.len());
+    let mut zero_flow_nodes = Vec::new();
+    for node in 0..graph.nodes.len() {
+        if is_zero_flow(node) {
+            zero_flow_nodes.push(node);
+            areas.push(Area::default());
+        } else {
+            areas.push(Area::from_nodes([node], graph));
+        }
+        node2area.push(node);
+    }
+
+    let mut excluded_nodes = Vec::new();
+    for &node in zero_flow_nodes.iter() {
+        // The heaviest flow in either direction, ties going to the lowest node index
+        let strongest = match options.zero_flow_policy {
+            ZeroFlowPolicy::Attach => graph
+                .get_edges(node, EdgeDirection::Out)
+                .map(|edge| (edge.weight, edge.target))
+                .chain(
+                    graph
+                        .get_edges(node, EdgeDirection::In)
+                        .map(|edge| (edge.weight, edge.source)),
+                )
+                .filter(|&(weight, other)| weight > 0 && !is_zero_flow(other))
+                .filter(|&(_, other)| {
+                    adjacency.is_none_or(|adjacency| adjacency.neighbours(node).contains(&other))
+                })
+                .max_by_key(|&(weight, other)| (weight, Reverse(other)))
+                .map(|(_, other)| other),
+            ZeroFlowPolicy::Exclude => None,
+        };
+        match strongest {
+            Some(other) => {
+                areas[other].add_node(node, graph);
+                node2area[node] = other;
+            }
+            None => {
+                areas[node].add_node(node, graph);
+                areas[node].unallocatable = true;
+                excluded_nodes.push(node);
+            }
+        }
+    }
+
+    InitialAreas {
+        areas,
+        node2area,
+        zero_flow_nodes,
+        excluded_nodes,
+    }
+}
+
+/// Calls `Options::progress`, if set, every `PROGRESS_INTERVAL` iterations
+pub(crate) fn report_progress(
+    options: &Options,
+    iteration: usize,
+    worst_score: f64,
+    remaining_areas: usize,
+) {
+    if let Some(progress) = options.progress {
+        if iteration % PROGRESS_INTERVAL == 0 {
+            progress(&Progress {
+                iteration,
+                worst_score,
+                remaining_areas,
+            });
+        }
+    }
+}
+
+/// Decides whether the dissolution loop should stop before dissolving another area.
+/// `remaining_areas` counts the non-empty areas that are not unallocatable.
+pub(crate) fn stop_reason(
+    worst_x_equation: f64,
+    remaining_areas: usize,
+    iteration: usize,
+    start: Instant,
+    params: &Parameters,
+    limits: &Limits,
+) -> Option<StopReason> {
+    if worst_x_equation >= params.threshold {
+        Some(StopReason::Converged)
+    } else if remaining_areas <= 1 {
+        Some(StopReason::SingleArea)
+    } else if limits.max_iterations.is_some_and(|max| iteration >= max) {
+        Some(StopReason::IterationLimit)
+    } else if limits
+        .timeout
+        .is_some_and(|timeout| start.elapsed() >= timeout)
+    {
+        Some(StopReason::Timeout)
+    } else {
+        None
+    }
+}
+
+/// Compares every area's statistics with a recomputation from the graph's edges, and checks that
+/// `node2area` agrees with the areas' node sets.
+///
+/// The recomputation sums edge weights directly rather than going through `Area::add_node`, so a
+/// bug in the incremental updates cannot hide itself.
+pub fn check_invariants(
+    graph: &Graph,
+    areas: &[Area],
+    node2area: &[usize],
+    iteration: usize,
+) -> Vec<Drift> {
+    let mut missing_nodes = vec![Vec::new(); areas.len()];
+    for (node, &area_index) in node2area.iter().enumerate() {
+        if area_index < areas.len() && !areas[area_index].nodes.contains(&node) {
+            missing_nodes[area_index].push(node);
+        }
+    }
+
+    areas
+        .iter()
+        .zip(missing_nodes)
+        .enumerate()
+        .filter_map(|(area_index, (area, missing_nodes))| {
+            let expected = recompute_stats(graph, area);
+            let found = area.stats();
+            let mut unmapped_nodes = area
+                .nodes
+                .iter()
+                .copied()
+                .filter(|&node| node2area[node] != area_index)
+                .collect::<Vec<NodeIndex>>();
+            unmapped_nodes.sort_unstable();
+            let drifted =
+                expected != found || !unmapped_nodes.is_empty() || !missing_nodes.is_empty();
+            drifted.then_some(Drift {
+                iteration,
+                area: area_index,
+                expected,
+                found,
+                unmapped_nodes,
+                missing_nodes,
+            })
+        })
+        .collect()
+}
+
+/// An area's statistics summed from the edges of its nodes
+fn recompute_stats(graph: &Graph, area: &Area) -> AreaStats {
+    let mut stats = AreaStats::default();
+    for &node in area.nodes.iter() {
+        for edge in graph.get_edges(node, EdgeDirection::In) {
+            stats.flow_to_area += edge.weight as u64;
+        }
+        for edge in graph.get_edges(node, EdgeDirection::Out) {
+            stats.flow_from_area += edge.weight as u64;
+            if area.nodes.contains(&edge.target) {
+                stats.self_containment += edge.weight as u64;
+            }
+        }
+    }
+    stats
+}
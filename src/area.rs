@@ -0,0 +1,227 @@
+use rustc_hash::FxHashSet;
+use serde::Serialize;
+use std::cmp::Reverse;
+
+use crate::graph::{EdgeDirection, Graph, Node};
+use crate::params::Parameters;
+
+type NodeIndex = usize;
+
+/// Flow totals of an area that the x-equation is computed from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AreaStats {
+    /// Jobs in the area, i.e. flows from anywhere into the area's nodes
+    pub flow_to_area: u64,
+    /// Resident workers in the area, i.e. flows from the area's nodes to anywhere
+    pub flow_from_area: u64,
+    /// Flows that both start and end in the area
+    pub self_containment: u64,
+}
+
+/// One row of the per-area summary output
+#[derive(Clone, Debug, Serialize)]
+pub struct AreaSummary {
+    pub area: usize,
+    pub name: String,
+    pub nodes: usize,
+    pub resident_workers: u64,
+    pub workplace_jobs: u64,
+    pub internal_flow: u64,
+    pub supply_self_containment: f64,
+    pub demand_self_containment: f64,
+    pub x_equation: f64,
+    /// Code of the node with the most resident workers
+    pub largest_origin: String,
+    /// Code of the node with the most jobs
+    pub largest_destination: String,
+    pub unallocatable: bool,
+}
+
+/// A set of nodes together with flow totals that are kept up to date as nodes are added and
+/// removed
+#[derive(Clone, Debug, Default)]
+pub struct Area {
+    pub nodes: FxHashSet<NodeIndex>,
+    pub flow_to_area: u64,
+    pub flow_from_area: u64,
+    pub self_containment: u64,
+    /// Set for areas of nodes that could not be placed anywhere; such areas are never dissolved
+    pub unallocatable: bool,
+}
+
+impl Area {
+    /// Builds an area from a set of nodes, computing its statistics from scratch
+    pub fn from_nodes(nodes: impl IntoIterator<Item = NodeIndex>, graph: &Graph) -> Area {
+        let mut area = Area::default();
+        for node in nodes {
+            area.add_node(node, graph);
+        }
+        area
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn stats(&self) -> AreaStats {
+        AreaStats {
+            flow_to_area: self.flow_to_area,
+            flow_from_area: self.flow_from_area,
+            self_containment: self.self_containment,
+        }
+    }
+
+    /// Adds a node and the flows between it and the nodes already in the area.
+    /// Does nothing if the node is already in the area.
+    pub fn add_node(&mut self, node: NodeIndex, graph: &Graph) {
+        if !self.nodes.insert(node) {
+            return;
+        }
+        self.flow_to_area += graph.nodes[node].in_degree as u64;
+        self.flow_from_area += graph.nodes[node].out_degree as u64;
+        self.self_containment += self.internal_flow_of(node, graph);
+    }
+
+    /// Removes a node and the flows between it and the rest of the area.
+    /// Does nothing if the node is not in the area.
+    pub fn remove_node(&mut self, node: NodeIndex, graph: &Graph) {
+        if !self.nodes.contains(&node) {
+            return;
+        }
+        // Internal flows have to be counted while the node is still a member so that its
+        // self-loop is included
+        self.self_containment -= self.internal_flow_of(node, graph);
+        self.flow_to_area -= graph.nodes[node].in_degree as u64;
+        self.flow_from_area -= graph.nodes[node].out_degree as u64;
+        self.nodes.remove(&node);
+    }
+
+    /// Flows between a member node and the area, counting its self-loop once
+    fn internal_flow_of(&self, node: NodeIndex, graph: &Graph) -> u64 {
+        // All out edges where the target is in the area (including self-loops)
+        let a = graph
+            .get_edges(node, EdgeDirection::Out)
+            .filter(|e| self.nodes.contains(&e.target))
+            .map(|e| e.weight as u64)
+            .sum::<u64>();
+
+        // All in edges where the source is in the area (excluding self-loops)
+        let b = graph
+            .get_edges(node, EdgeDirection::In)
+            .filter(|e| self.nodes.contains(&e.source) && e.source != e.target)
+            .map(|e| e.weight as u64)
+            .sum::<u64>();
+
+        a + b
+    }
+
+    /// Share of resident workers who also work in the area, or zero if it has none
+    pub fn supply_self_containment(&self) -> f64 {
+        share(self.self_containment as f64, self.flow_from_area as f64)
+    }
+
+    /// Share of jobs in the area filled by residents of the area, or zero if it has none
+    pub fn demand_self_containment(&self) -> f64 {
+        share(self.self_containment as f64, self.flow_to_area as f64)
+    }
+
+    /// Scores the area against the size and self-containment criteria; areas with a negative
+    /// score relative to `params.threshold` are not valid TTWAs.
+    pub fn x_equation(&self, params: &Parameters) -> f64 {
+        let size = self.flow_from_area as f64;
+        let self_containment = self
+            .supply_self_containment()
+            .min(self.demand_self_containment());
+
+        if size >= params.target_size && self_containment >= params.target_containment {
+            1.0 / 12.0
+        } else if self_containment >= params.target_containment {
+            params.tradeoff() * (params.min_size - size)
+        } else if size >= params.target_size {
+            self_containment - params.min_containment
+        } else {
+            self_containment - params.tradeoff() * size - params.intercept()
+        }
+    }
+
+    /// Summarises the area for output; `codes` maps node indices to area codes
+    pub fn summary(
+        &self,
+        area: usize,
+        name: &str,
+        graph: &Graph,
+        codes: &[String],
+        params: &Parameters,
+    ) -> AreaSummary {
+        // Ties go to the lowest node index so the result does not depend on set order
+        let largest_by = |degree: fn(&Node) -> u32| {
+            self.nodes
+                .iter()
+                .max_by_key(|&&node| (degree(&graph.nodes[node]), Reverse(node)))
+                .map(|&node| codes[node].clone())
+                .unwrap_or_default()
+        };
+        AreaSummary {
+            area,
+            name: name.to_owned(),
+            nodes: self.nodes.len(),
+            resident_workers: self.flow_from_area,
+            workplace_jobs: self.flow_to_area,
+            internal_flow: self.self_containment,
+            supply_self_containment: self.supply_self_containment(),
+            demand_self_containment: self.demand_self_containment(),
+            x_equation: self.x_equation(params),
+            largest_origin: largest_by(|node| node.out_degree),
+            largest_destination: largest_by(|node| node.in_degree),
+            unallocatable: self.unallocatable,
+        }
+    }
+}
+
+/// Divides, treating anything over a zero total as zero so that empty flows never produce NaN
+pub(crate) fn share(flow: f64, total: f64) -> f64 {
+    if total == 0.0 {
+        0.0
+    } else {
+        flow / total
+    }
+}
+
+/// How strongly `node` is tied to `area`, from the shares of the node's and the area's flows
+/// that go between them. Zero if there are no such flows.
+pub(crate) fn tij2(
+    graph: &Graph,
+    node: NodeIndex,
+    areas: &[Area],
+    area: usize,
+    node2area: &[usize],
+) -> f64 {
+    let area_to_node = flow_area_to_node(graph, node, area, node2area);
+    let node_to_area = flow_node_to_area(graph, node, area, node2area);
+
+    let to_area = areas[area].flow_to_area as f64;
+    let from_area = areas[area].flow_from_area as f64;
+
+    let a = share(node_to_area, graph.nodes[node].out_degree as f64);
+    let b = share(node_to_area, to_area);
+    let c = share(area_to_node, from_area);
+    let d = share(area_to_node, graph.nodes[node].in_degree as f64);
+
+    a * b + c * d
+}
+
+fn flow_area_to_node(graph: &Graph, node: NodeIndex, area: usize, node2area: &[usize]) -> f64 {
+    graph
+        .get_edges(node, EdgeDirection::In)
+        .filter(|e| node2area[e.source] == area)
+        .map(|e| e.weight)
+        .sum::<u32>() as f64
+}
+
+fn flow_node_to_area(graph: &Graph, node: NodeIndex, area: usize, node2area: &[usize]) -> f64 {
+    graph
+        .get_edges(node, EdgeDirection::Out)
+        .filter(|e| node2area[e.target] == area)
+        .map(|e| e.weight)
+        .sum::<u32>() as f64
+}
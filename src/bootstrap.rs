@@ -0,0 +1,95 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Poisson};
+use rayon::prelude::*;
+
+use crate::algorithm::{Options, TtwaResult};
+use crate::compare::compare_partitions;
+use crate::graph::{Edge, Graph};
+use crate::params::Parameters;
+use crate::partition::Partition;
+use crate::ttwa_v2::travel_to_work_areas;
+
+/// Draws a new set of flows with the same nodes, replacing each flow by a Poisson count with the
+/// original flow as its mean. Flows that were zero stay zero.
+///
+/// `replicate` picks an independent random stream for the given `seed`, so each replicate can be
+/// drawn on its own thread and still get the same flows.
+pub fn resample_flows(graph: &Graph, seed: u64, replicate: u64) -> Graph {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(replicate);
+    let edges = graph
+        .edges()
+        .map(|edge| {
+            let poisson = Poisson::new(edge.weight as f64).expect("flows are positive");
+            Edge {
+                weight: poisson.sample(&mut rng) as u32,
+                ..edge
+            }
+        })
+        .collect::<Vec<Edge>>();
+    Graph::from_edges(graph.nodes.len(), edges)
+}
+
+/// One bootstrap replicate compared against the TTWAs of the original flows
+pub struct Replicate {
+    pub replicate: usize,
+    pub result: TtwaResult,
+    pub adjusted_rand_index: f64,
+    /// Share of resident workers in nodes that stayed in the replicate's match of their area
+    pub worker_agreement: f64,
+    /// For each node, whether it left the replicate's match of its original area
+    pub switched: Vec<bool>,
+}
+
+/// Runs [`travel_to_work_areas`] on `replicates` resamples of the flows and compares each
+/// partition against `baseline`, the TTWAs of the original flows.
+///
+/// Replicates run in parallel on the current rayon thread pool. Each one depends only on `seed`
+/// and its index, so the results are the same for any number of threads.
+pub fn bootstrap(
+    graph: &Graph,
+    baseline: &Partition,
+    params: &Parameters,
+    options: &Options,
+    replicates: usize,
+    seed: u64,
+) -> Vec<Replicate> {
+    (0..replicates)
+        .into_par_iter()
+        .map(|replicate| {
+            let resampled = resample_flows(graph, seed, replicate as u64);
+            let result = travel_to_work_areas(&resampled, params, options);
+            // Weight nodes by the original flows so every replicate is measured the same way
+            let comparison = compare_partitions(baseline, &result.partition, graph);
+            let mut switched = vec![false; graph.nodes.len()];
+            for node in comparison.switched.iter() {
+                switched[node.node] = true;
+            }
+            Replicate {
+                replicate,
+                result,
+                adjusted_rand_index: comparison.adjusted_rand_index,
+                worker_agreement: comparison.worker_agreement,
+                switched,
+            }
+        })
+        .collect()
+}
+
+/// Share of replicates in which each node stayed in the match of its original area
+pub fn node_stability(replicates: &[Replicate], node_count: usize) -> Vec<f64> {
+    (0..node_count)
+        .map(|node| {
+            let stayed = replicates
+                .iter()
+                .filter(|replicate| !replicate.switched[node])
+                .count();
+            if replicates.is_empty() {
+                1.0
+            } else {
+                stayed as f64 / replicates.len() as f64
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use ttwa_detection::io::{read_names, write_lookup};
+use ttwa_detection::{
+    bootstrap, naming, node_stability, travel_to_work_areas, write_table, Options,
+};
+
+use super::{check_stdio, FlowInput, ParameterArgs, ThreadArgs};
+
+#[derive(clap::Args)]
+pub struct BootstrapArgs {
+    #[command(flatten)]
+    flows: FlowInput,
+    /// Directory for `ttwas.csv`, `replicates.csv` and `stability.csv`
+    out_dir: String,
+    /// Number of resampled flow sets to compute TTWAs for
+    #[arg(long, default_value_t = 100)]
+    replicates: usize,
+    /// Seed of the random resampling; the same seed gives the same replicates
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// CSV mapping area codes (first column) to names (second column), used to name each TTWA
+    #[arg(long)]
+    names: Option<String>,
+    #[command(flatten)]
+    threads: ThreadArgs,
+    #[command(flatten)]
+    params: ParameterArgs,
+}
+
+#[derive(Serialize)]
+struct ReplicateRow {
+    replicate: usize,
+    areas: usize,
+    iterations: usize,
+    stop_reason: String,
+    adjusted_rand_index: f64,
+    worker_agreement: f64,
+}
+
+#[derive(Serialize)]
+struct StabilityRow<'a> {
+    code: &'a str,
+    area: usize,
+    name: &'a str,
+    stability: f64,
+}
+
+/// Measures how stable the TTWAs are under random noise in the flows
+pub fn bootstrap_flows(args: BootstrapArgs) -> Result<()> {
+    check_stdio(
+        &[
+            Some(args.flows.input.as_str()),
+            args.params.config.as_deref(),
+            args.names.as_deref(),
+        ],
+        &[],
+    )?;
+    let params = args.params.resolve()?;
+    let (codes, graph) = args.flows.read()?;
+    let names = args
+        .names
+        .map(|names| read_names(Path::new(&names)))
+        .transpose()?;
+    let options = Options::default();
+
+    let result = travel_to_work_areas(&graph, &params, &options);
+    let (baseline, area_names) =
+        naming::sort_and_name_areas(result.partition, &graph, &codes, names.as_ref());
+    eprintln!(
+        "Found {} TTWAs; running {} replicates",
+        baseline.len(),
+        args.replicates
+    );
+    let replicates = args.threads.pool()?.install(|| {
+        bootstrap(
+            &graph,
+            &baseline,
+            &params,
+            &options,
+            args.replicates,
+            args.seed,
+        )
+    });
+    let stability = node_stability(&replicates, codes.len());
+
+    let out_dir = PathBuf::from(&args.out_dir);
+    std::fs::create_dir_all(&out_dir)
+        .with_context(|| format!("failed to create {}", out_dir.display()))?;
+    write_lookup(&out_dir.join("ttwas.csv"), &codes, &baseline, &area_names)?;
+    let rows = replicates
+        .iter()
+        .map(|replicate| ReplicateRow {
+            replicate: replicate.replicate,
+            areas: replicate.result.partition.len(),
+            iterations: replicate.result.iterations,
+            stop_reason: replicate.result.stop_reason.to_string(),
+            adjusted_rand_index: replicate.adjusted_rand_index,
+            worker_agreement: replicate.worker_agreement,
+        })
+        .collect::<Vec<_>>();
+    write_table(&out_dir.join("replicates.csv"), &rows)?;
+    let rows = codes
+        .iter()
+        .zip(stability.iter())
+        .enumerate()
+        .map(|(node, (code, &stability))| {
+            let area = baseline.node_to_area[node];
+            StabilityRow {
+                code,
+                area,
+                name: &area_names[area],
+                stability,
+            }
+        })
+        .collect::<Vec<_>>();
+    write_table(&out_dir.join("stability.csv"), &rows)?;
+
+    let unstable = stability.iter().filter(|&&share| share < 0.5).count();
+    eprintln!(
+        "{} of {} nodes left their TTWA in more than half of the replicates",
+        unstable,
+        codes.len()
+    );
+    Ok(())
+}
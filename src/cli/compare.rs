@@ -0,0 +1,120 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use ttwa_detection::io::read_lookup;
+use ttwa_detection::{compare_partitions, write_table, Partition};
+
+use super::{check_stdio, FlowInput};
+
+#[derive(clap::Args)]
+pub struct CompareArgs {
+    /// Flows used to weight nodes by their resident workers
+    #[command(flatten)]
+    flows: FlowInput,
+    /// Reference lookup, with node codes in the first column and area labels in the second
+    a: String,
+    /// Lookup to compare against the reference, in the same layout
+    b: String,
+    /// Write the best match in `b` of each area of `a` to this file, as JSON if it ends in
+    /// .json and CSV otherwise
+    #[arg(long)]
+    matches: Option<String>,
+    /// Write the nodes that switched area to this file, as JSON if it ends in .json and CSV
+    /// otherwise
+    #[arg(long)]
+    switched: Option<String>,
+}
+
+#[derive(Serialize)]
+struct MatchRow<'a> {
+    area_a: &'a str,
+    area_b: &'a str,
+    shared_workers: u64,
+    workers_a: u64,
+    workers_b: u64,
+    jaccard: f64,
+}
+
+#[derive(Serialize)]
+struct SwitchedRow<'a> {
+    code: &'a str,
+    area_a: &'a str,
+    area_b: &'a str,
+    matched_b: &'a str,
+}
+
+/// Reports how much two lookups of the same nodes differ
+pub fn compare(args: CompareArgs) -> Result<()> {
+    check_stdio(
+        &[
+            Some(args.flows.input.as_str()),
+            Some(args.a.as_str()),
+            Some(args.b.as_str()),
+        ],
+        &[args.matches.as_deref(), args.switched.as_deref()],
+    )?;
+    let (codes, graph) = args.flows.read()?;
+    let (a, labels_a) = Partition::from_lookup(&read_lookup(Path::new(&args.a))?, &codes, &graph)?;
+    let (b, labels_b) = Partition::from_lookup(&read_lookup(Path::new(&args.b))?, &codes, &graph)?;
+    let comparison = compare_partitions(&a, &b, &graph);
+
+    // The prose goes to stderr so that `--matches -` or `--switched -` write nothing but the table
+    // to stdout
+
+    eprintln!(
+        "Areas: {} in {}, {} in {}",
+        a.len(),
+        args.a,
+        b.len(),
+        args.b
+    );
+    eprintln!("Adjusted Rand index: {:.4}", comparison.adjusted_rand_index);
+    eprintln!(
+        "Normalised mutual information: {:.4}",
+        comparison.normalised_mutual_information
+    );
+    eprintln!(
+        "Variation of information: {:.4}",
+        comparison.variation_of_information
+    );
+    eprintln!(
+        "Worker-weighted agreement: {:.4}",
+        comparison.worker_agreement
+    );
+    eprintln!(
+        "{} of {} nodes switched area",
+        comparison.switched.len(),
+        codes.len()
+    );
+
+    if let Some(matches) = args.matches {
+        let rows = comparison
+            .best_matches
+            .iter()
+            .map(|best| MatchRow {
+                area_a: &labels_a[best.area_a],
+                area_b: &labels_b[best.area_b],
+                shared_workers: best.shared_workers,
+                workers_a: best.workers_a,
+                workers_b: best.workers_b,
+                jaccard: best.jaccard,
+            })
+            .collect::<Vec<_>>();
+        write_table(Path::new(&matches), &rows)?;
+    }
+    if let Some(switched) = args.switched {
+        let rows = comparison
+            .switched
+            .iter()
+            .map(|switched| SwitchedRow {
+                code: &codes[switched.node],
+                area_a: &labels_a[switched.area_a],
+                area_b: &labels_b[switched.area_b],
+                matched_b: &labels_b[switched.matched_b],
+            })
+            .collect::<Vec<_>>();
+        write_table(Path::new(&switched), &rows)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use anyhow::Result;
+use std::path::Path;
+
+use ttwa_detection::{evaluate_lookup, write_table};
+
+use super::{check_stdio, FlowInput, ParameterArgs};
+
+#[derive(clap::Args)]
+pub struct EvaluateArgs {
+    #[command(flatten)]
+    flows: FlowInput,
+    /// CSV with node codes in the first column and area labels in the second, such as the
+    /// output of `run` or the official TTWA lookup
+    lookup: String,
+    /// Write the score of each area to this file, as JSON if it ends in .json and CSV otherwise
+    #[arg(long)]
+    report: Option<String>,
+    #[command(flatten)]
+    params: ParameterArgs,
+}
+
+/// Scores each area of a lookup against the TTWA criteria
+pub fn evaluate(args: EvaluateArgs) -> Result<()> {
+    check_stdio(
+        &[
+            Some(args.flows.input.as_str()),
+            Some(args.lookup.as_str()),
+            args.params.config.as_deref(),
+        ],
+        &[args.report.as_deref()],
+    )?;
+    let params = args.params.resolve()?;
+    let (codes, graph) = args.flows.read()?;
+    let evaluations = evaluate_lookup(Path::new(&args.lookup), &graph, &codes, &params)?;
+
+    // The prose goes to stderr so that `--report -` writes nothing but the table to stdout
+
+    for area in evaluations.iter() {
+        let mut failures = Vec::new();
+        if area.below_min_size {
+            failures.push("below minimum size");
+        }
+        if area.below_min_containment {
+            failures.push("below minimum self-containment");
+        }
+        if area.fails {
+            failures.push("fails the x-equation");
+        }
+        eprintln!(
+            "Area {}: {} nodes, {} resident workers, self-containment {:.3} supply / {:.3} demand, x-equation {:.4}{}",
+            area.area,
+            area.nodes,
+            area.resident_workers,
+            area.supply_self_containment,
+            area.demand_self_containment,
+            area.x_equation,
+            if failures.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", failures.join(", "))
+            }
+        );
+    }
+    let failing = evaluations.iter().filter(|area| area.fails).count();
+    eprintln!(
+        "{} of {} areas meet the x-equation threshold",
+        evaluations.len() - failing,
+        evaluations.len()
+    );
+
+    if let Some(report) = args.report {
+        write_table(Path::new(&report), &evaluations)?;
+    }
+    Ok(())
+}
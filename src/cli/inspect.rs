@@ -0,0 +1,50 @@
+use anyhow::Result;
+
+use ttwa_detection::inspect::Distribution;
+use ttwa_detection::summarise_flows;
+
+use super::FlowInput;
+
+#[derive(clap::Args)]
+pub struct InspectArgs {
+    #[command(flatten)]
+    flows: FlowInput,
+    /// Print the summary as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// Prints an overview of a flow file
+pub fn inspect(args: InspectArgs) -> Result<()> {
+    let (_, graph) = args.flows.read()?;
+    let summary = summarise_flows(&graph);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+        return Ok(());
+    }
+
+    let distribution = |name: &str, distribution: &Distribution| {
+        println!(
+            "{}: min {}, median {}, mean {:.1}, max {}",
+            name, distribution.min, distribution.median, distribution.mean, distribution.max
+        );
+    };
+    println!("Nodes: {}", summary.nodes);
+    println!("Non-zero flows: {}", summary.flows);
+    println!("Total flow: {}", summary.total_flow);
+    println!(
+        "Flow within a node: {} ({:.1}%)",
+        summary.self_flow,
+        100.0 * summary.self_flow_share
+    );
+    println!("Nodes with no resident workers: {}", summary.zero_rows);
+    println!("Nodes with no jobs: {}", summary.zero_columns);
+    println!(
+        "Nodes with no flows to or from other nodes: {}",
+        summary.isolated_nodes
+    );
+    distribution("Resident workers per node", &summary.out_degree);
+    distribution("Jobs per node", &summary.in_degree);
+    distribution("Neighbours per node", &summary.neighbours);
+    Ok(())
+}
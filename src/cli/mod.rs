@@ -0,0 +1,144 @@
+//! Subcommands of the binary and the input handling they share
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+use ttwa_detection::compression::STDIO;
+use ttwa_detection::io::{read_adjacency_matrix_to_graph, read_edge_list_to_graph};
+use ttwa_detection::{EdgeListOptions, Graph, Parameters};
+
+pub mod bootstrap;
+pub mod compare;
+pub mod evaluate;
+pub mod inspect;
+pub mod run;
+pub mod sweep;
+
+/// A flow file and how to read it
+#[derive(clap::Args)]
+pub struct FlowInput {
+    /// Flow file, or `-` to read from standard input
+    pub input: String,
+    /// Layout of the input file
+    #[arg(long, value_enum, default_value_t = InputFormat::Matrix)]
+    pub format: InputFormat,
+    #[command(flatten)]
+    pub edge_list: EdgeListArgs,
+}
+
+impl FlowInput {
+    /// Reads the flows, returning the code of each node and the graph
+    pub fn read(&self) -> Result<(Vec<String>, Graph)> {
+        let path = Path::new(&self.input);
+        Ok(match self.format {
+            InputFormat::Matrix => read_adjacency_matrix_to_graph(path)?,
+            InputFormat::EdgeList => read_edge_list_to_graph(path, &self.edge_list.options())?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum InputFormat {
+    /// Square matrix with origins as rows and destinations as columns
+    Matrix,
+    /// Long format with one origin, destination and count per row
+    EdgeList,
+}
+
+/// Column names used when reading `--format edge-list` input
+#[derive(clap::Args)]
+pub struct EdgeListArgs {
+    #[arg(long, default_value = "origin")]
+    origin_column: String,
+    #[arg(long, default_value = "destination")]
+    destination_column: String,
+    #[arg(long, default_value = "count")]
+    count_column: String,
+    /// Keep origins that never appear as a destination instead of failing
+    #[arg(long)]
+    include_unknown_origins: bool,
+}
+
+impl EdgeListArgs {
+    fn options(&self) -> EdgeListOptions {
+        EdgeListOptions {
+            origin_column: self.origin_column.clone(),
+            destination_column: self.destination_column.clone(),
+            count_column: self.count_column.clone(),
+            include_unknown_origins: self.include_unknown_origins,
+        }
+    }
+}
+
+/// Overrides for the x-equation parameters, applied on top of the config file if one is given
+#[derive(clap::Args)]
+pub struct ParameterArgs {
+    /// TOML or JSON file with parameter values; missing fields use the ONS 2011 defaults
+    #[arg(long)]
+    pub config: Option<String>,
+    #[arg(long)]
+    target_size: Option<f64>,
+    #[arg(long)]
+    min_size: Option<f64>,
+    #[arg(long)]
+    target_containment: Option<f64>,
+    #[arg(long)]
+    min_containment: Option<f64>,
+    #[arg(long, allow_negative_numbers = true)]
+    threshold: Option<f64>,
+}
+
+impl ParameterArgs {
+    pub fn resolve(&self) -> Result<Parameters> {
+        let mut params = match &self.config {
+            Some(config) => Parameters::from_file(Path::new(config))?,
+            None => Parameters::default(),
+        };
+        if let Some(target_size) = self.target_size {
+            params.target_size = target_size;
+        }
+        if let Some(min_size) = self.min_size {
+            params.min_size = min_size;
+        }
+        if let Some(target_containment) = self.target_containment {
+            params.target_containment = target_containment;
+        }
+        if let Some(min_containment) = self.min_containment {
+            params.min_containment = min_containment;
+        }
+        if let Some(threshold) = self.threshold {
+            params.threshold = threshold;
+        }
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+/// How many threads to run independent TTWA computations on
+#[derive(clap::Args)]
+pub struct ThreadArgs {
+    /// Number of threads; defaults to one per CPU. Results do not depend on it.
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+impl ThreadArgs {
+    pub fn pool(&self) -> Result<rayon::ThreadPool> {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads.unwrap_or(0))
+            .build()
+            .context("failed to start the thread pool")
+    }
+}
+
+/// Fails if more than one input would read standard input or more than one output would write
+/// standard output
+pub fn check_stdio(inputs: &[Option<&str>], outputs: &[Option<&str>]) -> Result<()> {
+    let count = |paths: &[Option<&str>]| paths.iter().filter(|&&path| path == Some(STDIO)).count();
+    if count(inputs) > 1 {
+        bail!("only one input can be read from standard input");
+    }
+    if count(outputs) > 1 {
+        bail!("only one output can be written to standard output");
+    }
+    Ok(())
+}
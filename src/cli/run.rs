@@ -0,0 +1,382 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ttwa_detection::io;
+use ttwa_detection::{
+    eliminate_fragments, naming, refine_boundaries, ttwa_naive, ttwa_v2, Limits, Options,
+    OrphanPolicy, Progress, RegionalisationAlgorithm, StopReason, ZeroFlowPolicy,
+};
+
+use super::{check_stdio, FlowInput, ParameterArgs};
+
+#[derive(clap::Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    flows: FlowInput,
+    /// Where to write the area of each node, or `-` for standard output
+    output: Option<String>,
+    /// Stop after dissolving this many areas even if the threshold has not been met
+    max_iter: Option<usize>,
+    /// Stop after this many seconds even if the threshold has not been met
+    #[arg(long)]
+    timeout: Option<f64>,
+    /// CSV mapping area codes (first column) to names (second column), used to name each TTWA
+    /// after its largest workplaces
+    #[arg(long)]
+    names: Option<String>,
+    /// Write a per-TTWA summary to this file, as JSON if it ends in .json and CSV otherwise
+    #[arg(long)]
+    summary: Option<String>,
+    /// Recompute every area's statistics after each iteration and report any drift (slow)
+    #[arg(long)]
+    check_invariants: bool,
+    /// Which engine to run
+    #[arg(long, value_enum, default_value_t = Algorithm::V2)]
+    algorithm: Algorithm,
+    /// What to do with nodes of a dissolved area that have no commuting neighbour in another area
+    /// [default: defer]. Only used by `--algorithm v2`.
+    #[arg(long, value_enum)]
+    orphans: Option<Orphans>,
+    /// CSV of spatially adjacent area code pairs (first two columns), used by `--orphans adjacency`
+    /// and `--contiguous`. When given, TTWAs that are not contiguous are reported.
+    #[arg(long)]
+    adjacency: Option<String>,
+    /// Only move nodes to spatially adjacent areas so that TTWAs stay contiguous
+    #[arg(long)]
+    contiguous: bool,
+    /// After the run, move the detached pieces of each TTWA into adjacent TTWAs
+    #[arg(long)]
+    fix_fragments: bool,
+    /// After the run, move boundary nodes to neighbouring TTWAs they fit better, for at most
+    /// this many passes over the nodes
+    #[arg(long)]
+    refine: Option<usize>,
+    /// Exit with status 3, after writing the outputs, if the run stopped before every area met
+    /// the threshold
+    #[arg(long)]
+    fail_unconverged: bool,
+    /// What to do with nodes that have no resident workers or no jobs
+    #[arg(long, value_enum, default_value_t = ZeroFlow::Attach)]
+    zero_flow: ZeroFlow,
+    #[command(flatten)]
+    params: ParameterArgs,
+}
+
+/// Exit status for `--fail-unconverged`, distinct from the 1 of other errors and the 2 of
+/// command-line usage errors
+const UNCONVERGED_EXIT_CODE: i32 = 3;
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Algorithm {
+    /// Compare each reassigned node against every remaining area
+    Naive,
+    /// Compare each reassigned node against the areas of its commuting neighbours
+    V2,
+}
+
+impl Algorithm {
+    fn engine(self) -> Box<dyn RegionalisationAlgorithm> {
+        match self {
+            Algorithm::Naive => Box::new(ttwa_naive::Naive),
+            Algorithm::V2 => Box::new(ttwa_v2::V2),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Orphans {
+    /// Retry once the rest of the area is placed, then make the node unallocatable
+    Defer,
+    /// Retry once the rest of the area is placed, then join a spatially adjacent area
+    Adjacency,
+    /// Make the node unallocatable straight away
+    Singleton,
+}
+
+impl From<Orphans> for OrphanPolicy {
+    fn from(orphans: Orphans) -> OrphanPolicy {
+        match orphans {
+            Orphans::Defer => OrphanPolicy::Defer,
+            Orphans::Adjacency => OrphanPolicy::Adjacency,
+            Orphans::Singleton => OrphanPolicy::Singleton,
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ZeroFlow {
+    /// Start the node in the area of the node it has the heaviest flow with
+    Attach,
+    /// Leave the node out, in an unallocatable area of its own
+    Exclude,
+}
+
+impl From<ZeroFlow> for ZeroFlowPolicy {
+    fn from(zero_flow: ZeroFlow) -> ZeroFlowPolicy {
+        match zero_flow {
+            ZeroFlow::Attach => ZeroFlowPolicy::Attach,
+            ZeroFlow::Exclude => ZeroFlowPolicy::Exclude,
+        }
+    }
+}
+
+fn print_progress(progress: &Progress) {
+    eprintln!(
+        "Iteration {}: worst score {:.3}, {} areas remaining",
+        progress.iteration, progress.worst_score, progress.remaining_areas
+    );
+}
+
+/// Computes TTWAs and writes the area of each node
+pub fn run(args: RunArgs) -> Result<()> {
+    check_stdio(
+        &[
+            Some(args.flows.input.as_str()),
+            args.params.config.as_deref(),
+            args.names.as_deref(),
+            args.adjacency.as_deref(),
+        ],
+        &[args.output.as_deref(), args.summary.as_deref()],
+    )?;
+    let limits = Limits {
+        max_iterations: args.max_iter,
+        timeout: args
+            .timeout
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .context("--timeout must be a non-negative number of seconds")?,
+    };
+    let orphans = args.orphans.unwrap_or(Orphans::Defer);
+    if orphans == Orphans::Adjacency && args.adjacency.is_none() {
+        bail!("--orphans adjacency needs an --adjacency file");
+    }
+    if args.contiguous && args.adjacency.is_none() {
+        bail!("--contiguous needs an --adjacency file");
+    }
+    if args.fix_fragments && args.adjacency.is_none() {
+        bail!("--fix-fragments needs an --adjacency file");
+    }
+    if args.contiguous && matches!(args.algorithm, Algorithm::Naive) {
+        bail!("--contiguous is only supported by --algorithm v2");
+    }
+    if args.orphans.is_some() && matches!(args.algorithm, Algorithm::Naive) {
+        bail!("--orphans is only supported by --algorithm v2");
+    }
+    let params = args.params.resolve()?;
+    let (codes, graph) = args.flows.read()?;
+
+    let spatial_adjacency = args
+        .adjacency
+        .map(|adjacency| io::read_spatial_adjacency(Path::new(&adjacency), &codes))
+        .transpose()?
+        .map(Arc::new);
+    let options = Options {
+        limits,
+        check_invariants: args.check_invariants,
+        orphan_policy: orphans.into(),
+        spatial_adjacency: spatial_adjacency.clone(),
+        contiguous: args.contiguous,
+        zero_flow_policy: args.zero_flow.into(),
+        progress: Some(print_progress),
+    };
+    let result = args.algorithm.engine().run(&graph, &params, &options);
+    if !result.zero_flow_nodes.is_empty() {
+        eprintln!(
+            "{} nodes have no resident workers or no jobs; {} of them were excluded",
+            result.zero_flow_nodes.len(),
+            result.excluded_nodes.len()
+        );
+        for &node in result.excluded_nodes.iter() {
+            eprintln!("Excluded {}", codes[node]);
+        }
+    }
+    eprintln!(
+        "Stopped after {} iterations ({}), worst score {}",
+        result.iterations, result.stop_reason, result.worst_score
+    );
+    let names = args
+        .names
+        .map(|names| io::read_names(Path::new(&names)))
+        .transpose()?;
+    let mut partition = result.partition;
+    if let (true, Some(adjacency)) = (args.fix_fragments, &spatial_adjacency) {
+        let cleanup = eliminate_fragments(partition, &graph, adjacency, &params);
+        for fragment in cleanup.moves.iter() {
+            eprintln!(
+                "Moved {} detached nodes from area {} (now {:.3}) to area {} (now {:.3})",
+                fragment.nodes.len(),
+                fragment.from,
+                fragment.from_x_equation,
+                fragment.to,
+                fragment.to_x_equation
+            );
+        }
+        for fragment in cleanup.stranded.iter() {
+            eprintln!(
+                "Could not move {} detached nodes starting with {}: no other area is adjacent",
+                fragment.len(),
+                codes[fragment[0]]
+            );
+        }
+        if !cleanup.invalid_areas.is_empty() {
+            eprintln!(
+                "{} areas are below the threshold after moving fragments",
+                cleanup.invalid_areas.len()
+            );
+        }
+        partition = cleanup.partition;
+    }
+    if let Some(max_passes) = args.refine {
+        // Keep the areas that --fix-fragments made contiguous from being split again
+        let adjacency = spatial_adjacency
+            .as_deref()
+            .filter(|_| args.contiguous || args.fix_fragments);
+        let refinement = refine_boundaries(partition, &graph, &params, adjacency, max_passes);
+        for node_move in refinement.moves.iter() {
+            eprintln!(
+                "Pass {}: moved {} from area {} (tij2 {:.4}) to area {} (tij2 {:.4}), {} more internal flow",
+                node_move.pass,
+                codes[node_move.node],
+                node_move.from,
+                node_move.from_tij2,
+                node_move.to,
+                node_move.to_tij2,
+                node_move.self_containment_gain
+            );
+        }
+        eprintln!(
+            "Refinement moved {} nodes in {} passes",
+            refinement.moves.len(),
+            refinement.passes
+        );
+        partition = refinement.partition;
+    }
+    let (partition, area_names) =
+        naming::sort_and_name_areas(partition, &graph, &codes, names.as_ref());
+    let ttwas = &partition.areas;
+    eprintln!("Found {} TTWAs", ttwas.len());
+    let unallocatable = ttwas
+        .iter()
+        .map(|area| area.unallocatable)
+        .collect::<Vec<bool>>();
+    let unallocatable_count = unallocatable.iter().filter(|&&flag| flag).count();
+    if unallocatable_count > 0 {
+        eprintln!(
+            "{} of them are unallocatable groups of nodes that could not be placed in a TTWA",
+            unallocatable_count
+        );
+    }
+
+    if let Some(adjacency) = &spatial_adjacency {
+        for split in adjacency.non_contiguous_areas(&partition) {
+            let sizes = split
+                .fragments
+                .iter()
+                .map(|fragment| fragment.len().to_string())
+                .collect::<Vec<String>>();
+            eprintln!(
+                "Area {} ({}) is not contiguous: it is split into pieces of {} nodes",
+                split.area,
+                area_names[split.area],
+                sizes.join(", ")
+            );
+        }
+    }
+
+    let mut nodes = Vec::new();
+    let mut areas = Vec::new();
+
+    // Print the results
+    for ((area_id, area), area_nodes) in ttwas.iter().enumerate().zip(partition.sorted_nodes()) {
+        for node in area_nodes {
+            nodes.push(node);
+            areas.push(area_id);
+        }
+        eprintln!(
+            "Area {} ({}) has {} self containment, {} population, {} workforce{}",
+            area_id,
+            area_names[area_id],
+            area.self_containment,
+            area.flow_from_area,
+            area.flow_to_area,
+            if area.unallocatable {
+                " (unallocatable)"
+            } else {
+                ""
+            }
+        );
+    }
+    let area_metadata = ttwas
+        .iter()
+        .enumerate()
+        .map(|(area_id, area)| {
+            (
+                area_id,
+                [
+                    area.self_containment as f64,
+                    area.flow_from_area as f64,
+                    area.flow_to_area as f64,
+                ],
+            )
+        })
+        .collect::<HashMap<usize, [f64; 3]>>();
+    // Write the results to a file
+    if let Some(output) = args.output {
+        io::write_nodes_to_areas(
+            Path::new(&output),
+            &codes,
+            &nodes,
+            &areas,
+            &area_names,
+            &area_metadata,
+            &unallocatable,
+        )?;
+    }
+
+    if let Some(summary) = args.summary {
+        let summaries = ttwas
+            .iter()
+            .enumerate()
+            .map(|(area_id, area)| {
+                area.summary(area_id, &area_names[area_id], &graph, &codes, &params)
+            })
+            .collect::<Vec<_>>();
+        io::write_area_summaries(Path::new(&summary), &summaries)?;
+    }
+
+    for drift in result.drift.iter() {
+        if drift.expected != drift.found {
+            eprintln!(
+                "Iteration {}: area {} statistics drifted, expected {:?} but found {:?}",
+                drift.iteration, drift.area, drift.expected, drift.found
+            );
+        }
+        for &node in drift.unmapped_nodes.iter() {
+            eprintln!(
+                "Iteration {}: {} is in area {} but mapped elsewhere",
+                drift.iteration, codes[node], drift.area
+            );
+        }
+        for &node in drift.missing_nodes.iter() {
+            eprintln!(
+                "Iteration {}: {} is mapped to area {} but not in it",
+                drift.iteration, codes[node], drift.area
+            );
+        }
+    }
+    if !result.drift.is_empty() {
+        bail!("areas drifted {} times during the run", result.drift.len());
+    }
+    if args.fail_unconverged && result.stop_reason != StopReason::Converged {
+        eprintln!(
+            "Error: the run did not converge ({}), worst score {}",
+            result.stop_reason, result.worst_score
+        );
+        std::process::exit(UNCONVERGED_EXIT_CODE);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use ttwa_detection::io::{read_names, write_lookup};
+use ttwa_detection::sweep::{sweep, SweepGrid, SweepRow};
+use ttwa_detection::{naming, write_table, Options};
+
+use super::{check_stdio, FlowInput, ThreadArgs};
+
+#[derive(clap::Args)]
+pub struct SweepArgs {
+    #[command(flatten)]
+    flows: FlowInput,
+    /// TOML or JSON file with a list of values for each parameter; missing parameters keep the
+    /// ONS 2011 default
+    grid: String,
+    /// Directory for `results.csv` and one lookup per run under `partitions/`
+    out_dir: String,
+    /// CSV mapping area codes (first column) to names (second column), used to name each TTWA
+    #[arg(long)]
+    names: Option<String>,
+    #[command(flatten)]
+    threads: ThreadArgs,
+}
+
+/// Computes TTWAs for every combination of parameter values in a grid
+pub fn sweep_parameters(args: SweepArgs) -> Result<()> {
+    check_stdio(
+        &[
+            Some(args.flows.input.as_str()),
+            Some(args.grid.as_str()),
+            args.names.as_deref(),
+        ],
+        &[],
+    )?;
+    let grid = SweepGrid::from_file(Path::new(&args.grid))?;
+    let (codes, graph) = args.flows.read()?;
+    let names = args
+        .names
+        .map(|names| read_names(Path::new(&names)))
+        .transpose()?;
+
+    // Runs keep their position in the grid, so skipped combinations leave gaps in the numbering
+    let mut runs = Vec::new();
+    let mut parameter_sets = Vec::new();
+    for (run, params) in grid.parameter_sets().into_iter().enumerate() {
+        match params.validate() {
+            Ok(()) => {
+                runs.push(run);
+                parameter_sets.push(params);
+            }
+            Err(err) => eprintln!("Skipping run {run}: {err}"),
+        }
+    }
+    eprintln!("Running {} parameter sets", parameter_sets.len());
+    let results = args
+        .threads
+        .pool()?
+        .install(|| sweep(&graph, &parameter_sets, &Options::default()));
+
+    let out_dir = PathBuf::from(&args.out_dir);
+    let partitions = out_dir.join("partitions");
+    std::fs::create_dir_all(&partitions)
+        .with_context(|| format!("failed to create {}", partitions.display()))?;
+    let mut rows = Vec::new();
+    for ((&run, params), result) in runs.iter().zip(parameter_sets.iter()).zip(results) {
+        let row = SweepRow::new(run, params, &result);
+        eprintln!(
+            "Run {}: {} TTWAs, median self-containment {:.3}, {:.1}% of workers in failing areas",
+            run,
+            row.areas,
+            row.median_self_containment,
+            row.failing_worker_share * 100.0
+        );
+        rows.push(row);
+        let (partition, area_names) =
+            naming::sort_and_name_areas(result.partition, &graph, &codes, names.as_ref());
+        write_lookup(
+            &partitions.join(format!("run_{run:03}.csv")),
+            &codes,
+            &partition,
+            &area_names,
+        )?;
+    }
+    write_table(&out_dir.join("results.csv"), &rows)
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+
+use crate::graph::Graph;
+use crate::partition::Partition;
+
+/// The area of the second partition that overlaps most with an area of the first
+#[derive(Clone, Debug, PartialEq)]
+pub struct BestMatch {
+    pub area_a: usize,
+    pub area_b: usize,
+    /// Resident workers in nodes that belong to both areas
+    pub shared_workers: u64,
+    pub workers_a: u64,
+    pub workers_b: u64,
+    /// Shared workers as a share of the workers in either area
+    pub jaccard: f64,
+}
+
+/// A node whose area in the second partition is not the best match of its area in the first
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwitchedNode {
+    pub node: usize,
+    pub area_a: usize,
+    pub area_b: usize,
+    /// Best match of `area_a`, where the node would have been had it not moved
+    pub matched_b: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub adjusted_rand_index: f64,
+    /// Mutual information divided by the mean entropy of the two partitions
+    pub normalised_mutual_information: f64,
+    /// In nats; zero for identical partitions
+    pub variation_of_information: f64,
+    /// Share of resident workers in nodes that did not switch
+    pub worker_agreement: f64,
+    /// One entry per area of the first partition
+    pub best_matches: Vec<BestMatch>,
+    /// Sorted by node index
+    pub switched: Vec<SwitchedNode>,
+}
+
+/// Measures how far two partitions of the same graph differ.
+///
+/// The adjusted Rand index, normalised mutual information and variation of information count
+/// nodes and are symmetric. The best-match table, switched nodes and worker agreement treat `a`
+/// as the reference and weight nodes by their resident workers (`Node::out_degree`): each area of
+/// `a` is matched to the area of `b` with which it shares the most workers, then the most nodes,
+/// then the lowest index.
+pub fn compare_partitions(a: &Partition, b: &Partition, graph: &Graph) -> Comparison {
+    assert_eq!(a.node_to_area.len(), b.node_to_area.len());
+    let node_count = a.node_to_area.len();
+    let workers = |node: usize| graph.nodes[node].out_degree as u64;
+
+    // Nodes and workers in each pair of areas. An ordered map keeps the floating point sums
+    // below independent of hashing.
+    let mut overlap = BTreeMap::<(usize, usize), (u64, u64)>::new();
+    for node in 0..node_count {
+        let cell = overlap
+            .entry((a.node_to_area[node], b.node_to_area[node]))
+            .or_default();
+        cell.0 += 1;
+        cell.1 += workers(node);
+    }
+    let mut nodes_a = vec![0u64; a.len()];
+    let mut nodes_b = vec![0u64; b.len()];
+    let mut workers_a = vec![0u64; a.len()];
+    let mut workers_b = vec![0u64; b.len()];
+    for (&(area_a, area_b), &(nodes, shared)) in overlap.iter() {
+        nodes_a[area_a] += nodes;
+        nodes_b[area_b] += nodes;
+        workers_a[area_a] += shared;
+        workers_b[area_b] += shared;
+    }
+
+    let best_matches = (0..a.len())
+        .map(|area_a| {
+            let (&(_, area_b), &(_, shared)) = overlap
+                .range((area_a, 0)..(area_a + 1, 0))
+                .max_by_key(|(&(_, area_b), &(nodes, shared))| {
+                    (shared, nodes, std::cmp::Reverse(area_b))
+                })
+                .expect("every area has at least one node");
+            let union = workers_a[area_a] + workers_b[area_b] - shared;
+            BestMatch {
+                area_a,
+                area_b,
+                shared_workers: shared,
+                workers_a: workers_a[area_a],
+                workers_b: workers_b[area_b],
+                jaccard: if union == 0 {
+                    1.0
+                } else {
+                    shared as f64 / union as f64
+                },
+            }
+        })
+        .collect::<Vec<BestMatch>>();
+
+    let switched = (0..node_count)
+        .filter_map(|node| {
+            let area_a = a.node_to_area[node];
+            let area_b = b.node_to_area[node];
+            let matched_b = best_matches[area_a].area_b;
+            (area_b != matched_b).then_some(SwitchedNode {
+                node,
+                area_a,
+                area_b,
+                matched_b,
+            })
+        })
+        .collect::<Vec<SwitchedNode>>();
+    let total_workers = (0..node_count).map(workers).sum::<u64>();
+    let switched_workers = switched
+        .iter()
+        .map(|switched| workers(switched.node))
+        .sum::<u64>();
+    let worker_agreement = if total_workers == 0 {
+        if switched.is_empty() {
+            1.0
+        } else {
+            0.0
+        }
+    } else {
+        1.0 - switched_workers as f64 / total_workers as f64
+    };
+
+    let n = node_count as f64;
+    let entropy = |sizes: &[u64]| {
+        -sizes
+            .iter()
+            .map(|&size| size as f64 / n)
+            .filter(|&p| p > 0.0)
+            .map(|p| p * p.ln())
+            .sum::<f64>()
+    };
+    let entropy_a = entropy(&nodes_a);
+    let entropy_b = entropy(&nodes_b);
+    let mutual_information = overlap
+        .iter()
+        .map(|(&(area_a, area_b), &(nodes, _))| {
+            let p = nodes as f64 / n;
+            p * (p * n * n / (nodes_a[area_a] as f64 * nodes_b[area_b] as f64)).ln()
+        })
+        .sum::<f64>();
+    let mean_entropy = (entropy_a + entropy_b) / 2.0;
+    // Two single-area partitions are identical but have no entropy to normalise by
+    let normalised_mutual_information = if mean_entropy == 0.0 {
+        1.0
+    } else {
+        mutual_information / mean_entropy
+    };
+    let variation_of_information = (entropy_a + entropy_b - 2.0 * mutual_information).max(0.0);
+
+    let pairs = |count: u64| (count * count.saturating_sub(1) / 2) as f64;
+    let index = overlap
+        .values()
+        .map(|&(nodes, _)| pairs(nodes))
+        .sum::<f64>();
+    let pairs_a = nodes_a.iter().map(|&nodes| pairs(nodes)).sum::<f64>();
+    let pairs_b = nodes_b.iter().map(|&nodes| pairs(nodes)).sum::<f64>();
+    let expected = pairs_a * pairs_b / pairs(node_count as u64);
+    let maximum = (pairs_a + pairs_b) / 2.0;
+    // With fewer than two nodes there are no pairs to disagree on, and `expected` is 0 / 0
+    let adjusted_rand_index = if node_count < 2 || maximum == expected {
+        1.0
+    } else {
+        (index - expected) / (maximum - expected)
+    };
+
+    Comparison {
+        adjusted_rand_index,
+        normalised_mutual_information,
+        variation_of_information,
+        worker_agreement,
+        best_matches,
+        switched,
+    }
+}
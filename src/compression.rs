@@ -0,0 +1,129 @@
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How a file is compressed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Compression implied by a `.gz` or `.zst` extension
+    pub fn from_extension(path: &Path) -> Compression {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    fn from_magic(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// The extension of `path` once any compression extension is removed, so that `summary.json.gz`
+/// gives `json`
+pub fn inner_extension(path: &Path) -> Option<&str> {
+    let path = match Compression::from_extension(path) {
+        Compression::None => path,
+        _ => Path::new(path.file_stem()?),
+    };
+    path.extension().and_then(|ext| ext.to_str())
+}
+
+/// The path that stands for standard input or output
+pub const STDIO: &str = "-";
+
+/// Whether `path` is `-`, meaning standard input or output
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO)
+}
+
+/// Opens a file, or standard input for `-`, for reading. The input is decompressed if it starts
+/// with gzip or zstd magic bytes or has a `.gz` or `.zst` extension.
+pub fn open_input(path: &Path) -> io::Result<Box<dyn Read>> {
+    let mut reader: BufReader<Box<dyn Read>> = if is_stdio(path) {
+        BufReader::new(Box::new(io::stdin()))
+    } else {
+        BufReader::new(Box::new(File::open(path)?))
+    };
+    let compression = match Compression::from_magic(reader.fill_buf()?) {
+        Compression::None => Compression::from_extension(path),
+        compression => compression,
+    };
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+    })
+}
+
+/// A file or standard output being written, compressed according to its extension. Call
+/// [`Output::finish`] once done so that errors writing the end of a compressed stream are not
+/// lost.
+pub enum Output {
+    Plain(BufWriter<Box<dyn Write>>),
+    Gzip(GzEncoder<BufWriter<Box<dyn Write>>>),
+    Zstd(zstd::Encoder<'static, BufWriter<Box<dyn Write>>>),
+}
+
+impl Output {
+    /// Creates a file, or writes to standard output for `-`, compressing with gzip if the path
+    /// ends in `.gz` or zstd if it ends in `.zst`
+    pub fn create(path: &Path) -> io::Result<Output> {
+        let file: Box<dyn Write> = if is_stdio(path) {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(path)?)
+        };
+        let file = BufWriter::new(file);
+        Ok(match Compression::from_extension(path) {
+            Compression::None => Output::Plain(file),
+            Compression::Gzip => Output::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Compression::Zstd => Output::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    /// Writes the end of the compressed stream, if any, and flushes the file
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Output::Plain(file) => file,
+            Output::Gzip(encoder) => encoder.finish()?,
+            Output::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(file) => file.write(buf),
+            Output::Gzip(encoder) => encoder.write(buf),
+            Output::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(file) => file.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+            Output::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
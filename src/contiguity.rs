@@ -0,0 +1,120 @@
+use crate::area::tij2;
+use crate::graph::Graph;
+use crate::params::Parameters;
+use crate::partition::Partition;
+use crate::spatial::SpatialAdjacency;
+
+/// A fragment moved by [`eliminate_fragments`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FragmentMove {
+    /// The fragment's nodes, sorted by index
+    pub nodes: Vec<usize>,
+    pub from: usize,
+    pub to: usize,
+    /// Scores of the two areas after the move
+    pub from_x_equation: f64,
+    pub to_x_equation: f64,
+}
+
+pub struct FragmentCleanup {
+    pub partition: Partition,
+    pub moves: Vec<FragmentMove>,
+    /// Fragments that could not be moved because no other area is adjacent to them, sorted by
+    /// lowest node
+    pub stranded: Vec<Vec<usize>>,
+    /// Areas whose x-equation is below `Parameters::threshold` after the moves
+    pub invalid_areas: Vec<usize>,
+}
+
+/// Moves the pieces of non-contiguous areas into adjacent areas until every area is contiguous.
+///
+/// Each area keeps its largest piece. The other pieces are moved one at a time, smallest first
+/// and then by lowest node, to the spatially adjacent area for which the sum of the piece's
+/// nodes' tij2 is highest, with ties going to the lowest index. A moved piece always touches the
+/// area it joins, so no move splits an area further and the pass ends. Pieces with no adjacent
+/// area, such as islands, are left where they are and reported. Unallocatable areas neither give
+/// nor receive nodes.
+///
+/// Moves can push an area below the x-equation threshold; each move records both areas' scores
+/// and the areas left invalid are reported.
+pub fn eliminate_fragments(
+    mut partition: Partition,
+    graph: &Graph,
+    adjacency: &SpatialAdjacency,
+    params: &Parameters,
+) -> FragmentCleanup {
+    let mut moves = Vec::new();
+    let mut stranded: Vec<Vec<usize>> = Vec::new();
+
+    loop {
+        let fragment = adjacency
+            .non_contiguous_areas(&partition)
+            .into_iter()
+            .filter(|split| !partition.areas[split.area].unallocatable)
+            .flat_map(|split| {
+                let area = split.area;
+                split
+                    .fragments
+                    .into_iter()
+                    .skip(1)
+                    .map(move |fragment| (area, fragment))
+            })
+            .filter(|(_, fragment)| !stranded.contains(fragment))
+            .min_by(|(_, a), (_, b)| a.len().cmp(&b.len()).then(a[0].cmp(&b[0])));
+        let Some((from, nodes)) = fragment else {
+            break;
+        };
+
+        let mut candidates = nodes
+            .iter()
+            .flat_map(|&node| adjacency.neighbours(node).iter())
+            .map(|&neighbour| partition.node_to_area[neighbour])
+            .filter(|&area| area != from && !partition.areas[area].unallocatable)
+            .collect::<Vec<usize>>();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let mut best: Option<(usize, f64)> = None;
+        for area in candidates {
+            let score = nodes
+                .iter()
+                .map(|&node| tij2(graph, node, &partition.areas, area, &partition.node_to_area))
+                .sum::<f64>();
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((area, score));
+            }
+        }
+        let Some((to, _)) = best else {
+            stranded.push(nodes);
+            continue;
+        };
+
+        for &node in nodes.iter() {
+            partition.areas[from].remove_node(node, graph);
+            partition.areas[to].add_node(node, graph);
+            partition.node_to_area[node] = to;
+        }
+        moves.push(FragmentMove {
+            nodes,
+            from,
+            to,
+            from_x_equation: partition.areas[from].x_equation(params),
+            to_x_equation: partition.areas[to].x_equation(params),
+        });
+    }
+
+    stranded.sort();
+    let invalid_areas = partition
+        .areas
+        .iter()
+        .enumerate()
+        .filter(|(_, area)| !area.unallocatable && area.x_equation(params) < params.threshold)
+        .map(|(area, _)| area)
+        .collect();
+    FragmentCleanup {
+        partition,
+        moves,
+        stranded,
+        invalid_areas,
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::graph::Graph;
+use crate::io::read_lookup;
+use crate::params::Parameters;
+use crate::partition::Partition;
+
+/// How one area of a lookup measures up against the TTWA criteria
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AreaEvaluation {
+    /// Label of the area in the lookup
+    pub area: String,
+    pub nodes: usize,
+    /// Resident workers, the size measure of the x-equation
+    pub resident_workers: u64,
+    pub workplace_jobs: u64,
+    pub supply_self_containment: f64,
+    pub demand_self_containment: f64,
+    pub x_equation: f64,
+    /// Fewer resident workers than `Parameters::min_size`
+    pub below_min_size: bool,
+    /// The smaller self-containment is below `Parameters::min_containment`
+    pub below_min_containment: bool,
+    /// The x-equation score is below `Parameters::threshold`, so the area is not a valid TTWA
+    pub fails: bool,
+}
+
+/// Scores every area of a partition without changing it. `labels` names each area.
+pub fn evaluate_partition(
+    partition: &Partition,
+    labels: &[String],
+    params: &Parameters,
+) -> Vec<AreaEvaluation> {
+    partition
+        .areas
+        .iter()
+        .zip(labels)
+        .map(|(area, label)| {
+            let supply = area.supply_self_containment();
+            let demand = area.demand_self_containment();
+            let x_equation = area.x_equation(params);
+            AreaEvaluation {
+                area: label.clone(),
+                nodes: area.nodes.len(),
+                resident_workers: area.flow_from_area,
+                workplace_jobs: area.flow_to_area,
+                supply_self_containment: supply,
+                demand_self_containment: demand,
+                x_equation,
+                below_min_size: (area.flow_from_area as f64) < params.min_size,
+                below_min_containment: supply.min(demand) < params.min_containment,
+                fails: x_equation < params.threshold,
+            }
+        })
+        .collect()
+}
+
+/// Reads a node-to-area lookup such as the official TTWAs and scores each of its areas against
+/// the flows in `graph`, whose nodes have the given `codes`. The lookup must cover exactly the
+/// nodes of the graph. Areas are ordered as by [`Partition::from_lookup`].
+pub fn evaluate_lookup(
+    path: &Path,
+    graph: &Graph,
+    codes: &[String],
+    params: &Parameters,
+) -> Result<Vec<AreaEvaluation>> {
+    let lookup = read_lookup(path)?;
+    let (partition, labels) = Partition::from_lookup(&lookup, codes, graph)?;
+    Ok(evaluate_partition(&partition, &labels, params))
+}
@@ -1,4 +1,4 @@
-use std::rc::Rc;
+use std::ops::Range;
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub struct Node {
@@ -7,65 +7,154 @@ pub struct Node {
     pub out_degree: u32,
 }
 
-#[allow(dead_code)]
-impl Node {
-    fn new(id: usize) -> Node {
-        Node {
-            id,
-            in_degree: 0,
-            out_degree: 0,
-        }
-    }
-}
-
-#[derive(PartialEq, Clone, Debug, Eq, Hash)]
+#[derive(PartialEq, Clone, Copy, Debug, Eq, Hash)]
 pub struct Edge {
     pub source: usize,
     pub target: usize,
     pub weight: u32,
 }
 
+/// Edges grouped by node in compressed sparse row layout. The edges of node `i` are stored at
+/// positions `offsets[i]..offsets[i + 1]` of `neighbors` and `weights`, sorted by neighbor.
+#[derive(Debug)]
+struct Adjacency {
+    offsets: Vec<usize>,
+    neighbors: Vec<u32>,
+    weights: Vec<u32>,
+}
+
+impl Adjacency {
+    /// Builds the layout from edges given as (node, neighbor, weight), sorted by node then neighbor
+    fn from_sorted(
+        node_count: usize,
+        edges: impl IntoIterator<Item = (usize, usize, u32)>,
+    ) -> Self {
+        let edges = edges.into_iter();
+        let mut adjacency = Adjacency {
+            offsets: Vec::with_capacity(node_count + 1),
+            neighbors: Vec::with_capacity(edges.size_hint().0),
+            weights: Vec::with_capacity(edges.size_hint().0),
+        };
+        adjacency.offsets.push(0);
+        for (node, neighbor, weight) in edges {
+            while adjacency.offsets.len() <= node {
+                adjacency.offsets.push(adjacency.neighbors.len());
+            }
+            adjacency.neighbors.push(neighbor as u32);
+            adjacency.weights.push(weight);
+        }
+        while adjacency.offsets.len() <= node_count {
+            adjacency.offsets.push(adjacency.neighbors.len());
+        }
+        adjacency.neighbors.shrink_to_fit();
+        adjacency.weights.shrink_to_fit();
+        adjacency
+    }
+
+    /// Swaps the roles of node and neighbor using a counting sort, which keeps neighbors sorted
+    fn transpose(&self) -> Self {
+        let node_count = self.offsets.len() - 1;
+        let mut offsets = vec![0; node_count + 1];
+        for &neighbor in self.neighbors.iter() {
+            offsets[neighbor as usize + 1] += 1;
+        }
+        for i in 0..node_count {
+            offsets[i + 1] += offsets[i];
+        }
+
+        let mut next = offsets.clone();
+        let mut neighbors = vec![0; self.neighbors.len()];
+        let mut weights = vec![0; self.weights.len()];
+        for node in 0..node_count {
+            for k in self.range(node) {
+                let slot = &mut next[self.neighbors[k] as usize];
+                neighbors[*slot] = node as u32;
+                weights[*slot] = self.weights[k];
+                *slot += 1;
+            }
+        }
+
+        Adjacency {
+            offsets,
+            neighbors,
+            weights,
+        }
+    }
+
+    fn range(&self, node: usize) -> Range<usize> {
+        self.offsets[node]..self.offsets[node + 1]
+    }
+
+    fn heap_size(&self) -> usize {
+        self.offsets.capacity() * std::mem::size_of::<usize>()
+            + self.neighbors.capacity() * std::mem::size_of::<u32>()
+            + self.weights.capacity() * std::mem::size_of::<u32>()
+    }
+}
+
+/// A weighted directed graph stored once by source (out edges) and once by target (in edges)
 #[derive(Debug)]
 pub struct Graph {
     pub nodes: Vec<Node>,
-    pub edges: Vec<Rc<Edge>>,
-    node_to_in_edges: Vec<Vec<Rc<Edge>>>,
-    node_to_out_edges: Vec<Vec<Rc<Edge>>>,
+    out_edges: Adjacency,
+    in_edges: Adjacency,
 }
 
+/// Returned when an adjacency matrix has a row whose length differs from the number of rows
+#[derive(Debug, thiserror::Error)]
+#[error("adjacency matrix row {row} has {found} entries but there are {expected} rows")]
+pub struct NotSquareError {
+    pub row: usize,
+    pub expected: usize,
+    pub found: usize,
+}
+
+#[derive(Clone, Copy)]
 pub enum EdgeDirection {
     In,
     Out,
 }
 
-#[allow(dead_code)]
 impl Graph {
-    fn new() -> Graph {
+    fn from_out_edges(out_edges: Adjacency) -> Graph {
+        let in_edges = out_edges.transpose();
+        let nodes = (0..out_edges.offsets.len() - 1)
+            .map(|id| Node {
+                id,
+                in_degree: in_edges.range(id).map(|k| in_edges.weights[k]).sum(),
+                out_degree: out_edges.range(id).map(|k| out_edges.weights[k]).sum(),
+            })
+            .collect();
         Graph {
-            nodes: Vec::new(),
-            edges: Vec::new(),
-            node_to_in_edges: Vec::new(),
-            node_to_out_edges: Vec::new(),
+            nodes,
+            out_edges,
+            in_edges,
         }
     }
-    pub fn from_adjacency_matrix(adjacency: Vec<Vec<u32>>) -> Graph {
-        let mut graph = Graph::new();
-
-        for index in 0..adjacency.len() {
-            graph.add_node(Node::new(index));
-        }
 
-        for (i, row) in adjacency.iter().enumerate() {
-            for (j, &weight) in row.iter().enumerate() {
-                if weight > 0 {
-                    graph.add_edge(Edge {
-                        source: i,
-                        target: j,
-                        weight,
-                    });
-                }
+    /// Builds a graph from a square matrix where entry (i, j) is the flow from node i to node j.
+    pub fn from_adjacency_matrix(adjacency: Vec<Vec<u32>>) -> Result<Graph, NotSquareError> {
+        for (row, values) in adjacency.iter().enumerate() {
+            if values.len() != adjacency.len() {
+                return Err(NotSquareError {
+                    row,
+                    expected: adjacency.len(),
+                    found: values.len(),
+                });
             }
         }
+
+        let out_edges = Adjacency::from_sorted(
+            adjacency.len(),
+            adjacency.iter().enumerate().flat_map(|(i, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, &weight)| weight > 0)
+                    .map(move |(j, &weight)| (i, j, weight))
+            }),
+        );
+        let graph = Graph::from_out_edges(out_edges);
+
         // Node's out_degree should be equal to the row-sum for that node in the adjacency matrix
         // Node's in_degree should be equal to the column-sum for that node in the adjacency matrix
         assert_eq!(
@@ -92,14 +181,40 @@ impl Graph {
 
         // Sum of all edge weights should equal the sum of all values in the adjacency matrix
         assert_eq!(
-            graph.edges.iter().map(|edge| edge.weight).sum::<u32>(),
+            graph.edges().map(|edge| edge.weight).sum::<u32>(),
             adjacency
                 .iter()
                 .map(|row| row.iter().sum::<u32>())
                 .sum::<u32>()
         );
 
-        graph
+        Ok(graph)
+    }
+
+    /// Builds a graph with `node_count` nodes from a list of weighted edges.
+    /// Edges with zero weight are skipped and the weights of repeated (source, target) pairs are
+    /// added together.
+    pub fn from_edges(node_count: usize, edges: impl IntoIterator<Item = Edge>) -> Graph {
+        let mut edges = edges
+            .into_iter()
+            .filter(|edge| edge.weight > 0)
+            .collect::<Vec<Edge>>();
+        edges.sort_unstable_by_key(|edge| (edge.source, edge.target));
+        edges.dedup_by(|edge, kept| {
+            let same_pair = edge.source == kept.source && edge.target == kept.target;
+            if same_pair {
+                kept.weight += edge.weight;
+            }
+            same_pair
+        });
+
+        let out_edges = Adjacency::from_sorted(
+            node_count,
+            edges
+                .into_iter()
+                .map(|edge| (edge.source, edge.target, edge.weight)),
+        );
+        Graph::from_out_edges(out_edges)
     }
 
     pub fn in_degrees(&self) -> Vec<u32> {
@@ -110,47 +225,61 @@ impl Graph {
         self.nodes.iter().map(|node| node.out_degree).collect()
     }
 
-    fn add_node(&mut self, node: Node) {
-        self.nodes.insert(node.id, node);
-        self.node_to_in_edges.push(Vec::new());
-        self.node_to_out_edges.push(Vec::new());
+    pub fn edge_count(&self) -> usize {
+        self.out_edges.neighbors.len()
     }
 
-    fn add_edge(&mut self, edge: Edge) {
-        let edge = Rc::new(edge);
-        // Update degrees
-        // This won't work if we've removed nodes
-        let target = edge.target;
-        let source = edge.source;
-        self.nodes[source].out_degree += edge.weight;
-        self.nodes[target].in_degree += edge.weight;
-
-        self.edges.push(edge.clone());
-        self.node_to_in_edges[target].push(edge.clone());
-        self.node_to_out_edges[source].push(edge.clone());
+    /// All edges, ordered by source and then target
+    pub fn edges(&self) -> impl Iterator<Item = Edge> + '_ {
+        (0..self.nodes.len()).flat_map(move |node| self.get_edges(node, EdgeDirection::Out))
     }
 
     pub fn get_edges(
         &self,
         node_index: usize,
         direction: EdgeDirection,
-    ) -> impl Iterator<Item = &'_ Rc<Edge>> {
-        match direction {
-            EdgeDirection::In => self.node_to_in_edges[node_index].iter(),
-            EdgeDirection::Out => self.node_to_out_edges[node_index].iter(),
-        }
+    ) -> impl Iterator<Item = Edge> + '_ {
+        let adjacency = match direction {
+            EdgeDirection::In => &self.in_edges,
+            EdgeDirection::Out => &self.out_edges,
+        };
+        adjacency.range(node_index).map(move |k| {
+            let neighbor = adjacency.neighbors[k] as usize;
+            let weight = adjacency.weights[k];
+            match direction {
+                EdgeDirection::In => Edge {
+                    source: neighbor,
+                    target: node_index,
+                    weight,
+                },
+                EdgeDirection::Out => Edge {
+                    source: node_index,
+                    target: neighbor,
+                    weight,
+                },
+            }
+        })
     }
 
     pub fn get_neighbors(&self, node_index: usize) -> impl Iterator<Item = usize> + '_ {
-        self.node_to_in_edges[node_index]
-            .iter()
-            .filter(move |edge| edge.source != node_index)
-            .map(|edge| edge.source)
-            .chain(
-                self.node_to_out_edges[node_index]
-                    .iter()
-                    .filter(move |edge| edge.target != node_index)
-                    .map(|edge| edge.target),
-            )
+        let sources = self
+            .in_edges
+            .range(node_index)
+            .map(|k| self.in_edges.neighbors[k]);
+        let targets = self
+            .out_edges
+            .range(node_index)
+            .map(|k| self.out_edges.neighbors[k]);
+        sources
+            .chain(targets)
+            .map(|neighbor| neighbor as usize)
+            .filter(move |&neighbor| neighbor != node_index)
+    }
+
+    /// Bytes allocated on the heap for the nodes and both edge layouts
+    pub fn heap_size(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<Node>()
+            + self.out_edges.heap_size()
+            + self.in_edges.heap_size()
     }
 }
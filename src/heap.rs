@@ -0,0 +1,108 @@
+use std::cmp::Ordering;
+
+const ABSENT: usize = usize::MAX;
+
+/// Binary min-heap over the items `0..capacity`, keyed by a score that can be changed in place.
+///
+/// Items with equal scores come out lowest index first, and NaN scores are treated as positive
+/// infinity, so the minimum is always the item a linear scan with `<` would have found first.
+pub struct IndexedMinHeap {
+    heap: Vec<usize>,
+    positions: Vec<usize>,
+    keys: Vec<f64>,
+}
+
+impl IndexedMinHeap {
+    pub fn new(capacity: usize) -> IndexedMinHeap {
+        IndexedMinHeap {
+            heap: Vec::with_capacity(capacity),
+            positions: vec![ABSENT; capacity],
+            keys: vec![f64::INFINITY; capacity],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// The item with the smallest score, and that score
+    pub fn peek(&self) -> Option<(usize, f64)> {
+        self.heap.first().map(|&item| (item, self.keys[item]))
+    }
+
+    /// Inserts `item`, or changes its score if it is already in the heap
+    pub fn push_or_update(&mut self, item: usize, key: f64) {
+        self.keys[item] = if key.is_nan() { f64::INFINITY } else { key };
+        let position = self.positions[item];
+        if position == ABSENT {
+            self.heap.push(item);
+            self.positions[item] = self.heap.len() - 1;
+            self.sift_up(self.heap.len() - 1);
+        } else {
+            let position = self.sift_up(position);
+            self.sift_down(position);
+        }
+    }
+
+    /// Removes `item` from the heap if it is present
+    pub fn remove(&mut self, item: usize) {
+        let position = self.positions[item];
+        if position == ABSENT {
+            return;
+        }
+        let last = self.heap.len() - 1;
+        self.swap(position, last);
+        self.heap.pop();
+        self.positions[item] = ABSENT;
+        if position < self.heap.len() {
+            let position = self.sift_up(position);
+            self.sift_down(position);
+        }
+    }
+
+    fn less(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.heap[a], self.heap[b]);
+        match self.keys[a].partial_cmp(&self.keys[b]) {
+            Some(Ordering::Less) => true,
+            Some(Ordering::Equal) => a < b,
+            _ => false,
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.positions[self.heap[a]] = a;
+        self.positions[self.heap[b]] = b;
+    }
+
+    fn sift_up(&mut self, mut position: usize) -> usize {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if !self.less(position, parent) {
+                break;
+            }
+            self.swap(position, parent);
+            position = parent;
+        }
+        position
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let left = 2 * position + 1;
+            let right = left + 1;
+            let mut smallest = position;
+            if left < self.heap.len() && self.less(left, smallest) {
+                smallest = left;
+            }
+            if right < self.heap.len() && self.less(right, smallest) {
+                smallest = right;
+            }
+            if smallest == position {
+                break;
+            }
+            self.swap(position, smallest);
+            position = smallest;
+        }
+    }
+}
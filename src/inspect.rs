@@ -0,0 +1,121 @@
+use serde::Serialize;
+
+use crate::area::share;
+use crate::graph::{EdgeDirection, Graph};
+
+/// Spread of a per-node count
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Distribution {
+    pub min: u64,
+    pub median: f64,
+    pub mean: f64,
+    pub max: u64,
+}
+
+impl Distribution {
+    fn from_values(mut values: Vec<u64>) -> Distribution {
+        if values.is_empty() {
+            return Distribution {
+                min: 0,
+                median: 0.0,
+                mean: 0.0,
+                max: 0,
+            };
+        }
+        values.sort_unstable();
+        let middle = values.len() / 2;
+        let median = if values.len() % 2 == 0 {
+            (values[middle - 1] + values[middle]) as f64 / 2.0
+        } else {
+            values[middle] as f64
+        };
+        Distribution {
+            min: values[0],
+            median,
+            mean: values.iter().sum::<u64>() as f64 / values.len() as f64,
+            max: values[values.len() - 1],
+        }
+    }
+}
+
+/// Overview of a flow matrix, to check an input before running the algorithm on it
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FlowSummary {
+    pub nodes: usize,
+    /// Non-zero flows, including flows from a node to itself
+    pub flows: usize,
+    pub total_flow: u64,
+    /// Flow from each node to itself
+    pub self_flow: u64,
+    pub self_flow_share: f64,
+    /// Nodes with no resident workers
+    pub zero_rows: usize,
+    /// Nodes with no jobs
+    pub zero_columns: usize,
+    /// Nodes with no flows to or from any other node
+    pub isolated_nodes: usize,
+    /// Resident workers per node
+    pub out_degree: Distribution,
+    /// Jobs per node
+    pub in_degree: Distribution,
+    /// Other nodes each node has flows to or from
+    pub neighbours: Distribution,
+}
+
+/// Counts the nodes and flows of a graph and how the flows are spread across nodes
+pub fn summarise_flows(graph: &Graph) -> FlowSummary {
+    let node_count = graph.nodes.len();
+    let total_flow = graph
+        .nodes
+        .iter()
+        .map(|node| node.out_degree as u64)
+        .sum::<u64>();
+    let self_flow = (0..node_count)
+        .flat_map(|node| graph.get_edges(node, EdgeDirection::Out))
+        .filter(|edge| edge.source == edge.target)
+        .map(|edge| edge.weight as u64)
+        .sum::<u64>();
+    // A node can be both a source and a target of another, so count each neighbour once
+    let neighbours = (0..node_count)
+        .map(|node| {
+            let mut neighbours = graph.get_neighbors(node).collect::<Vec<usize>>();
+            neighbours.sort_unstable();
+            neighbours.dedup();
+            neighbours.len() as u64
+        })
+        .collect::<Vec<u64>>();
+
+    FlowSummary {
+        nodes: node_count,
+        flows: graph.edge_count(),
+        total_flow,
+        self_flow,
+        self_flow_share: share(self_flow as f64, total_flow as f64),
+        zero_rows: graph
+            .nodes
+            .iter()
+            .filter(|node| node.out_degree == 0)
+            .count(),
+        zero_columns: graph
+            .nodes
+            .iter()
+            .filter(|node| node.in_degree == 0)
+            .count(),
+        isolated_nodes: neighbours.iter().filter(|&&count| count == 0).count(),
+        out_degree: Distribution::from_values(
+            graph
+                .nodes
+                .iter()
+                .map(|node| node.out_degree as u64)
+                .collect(),
+        ),
+        in_degree: Distribution::from_values(
+            graph
+                .nodes
+                .iter()
+                .map(|node| node.in_degree as u64)
+                .collect(),
+        ),
+        neighbours: Distribution::from_values(neighbours),
+    }
+}
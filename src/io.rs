@@ -1,39 +1,472 @@
-use crate::graph::Graph;
-use anyhow::Result;
-use std::collections::HashMap;
-use std::path::Path;
+use crate::area::AreaSummary;
+use crate::compression::{inner_extension, open_input, Output};
+use crate::graph::{Edge, Graph};
+use crate::partition::Partition;
+use crate::spatial::SpatialAdjacency;
+use anyhow::{anyhow, bail, Result};
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Problems found while reading a flow matrix, located by file, line and column header. The
+/// count errors are also reported by the edge-list reader.
+#[derive(Debug, thiserror::Error)]
+pub enum MatrixError {
+    #[error("failed to read {}: {source}", path.display())]
+    Csv {
+        path: PathBuf,
+        #[source]
+        source: csv::Error,
+    },
+    #[error("{}: line {line}, column {column:?}: {value:?} is not a valid count", path.display())]
+    InvalidCount {
+        path: PathBuf,
+        line: u64,
+        column: String,
+        value: String,
+    },
+    #[error("{}: line {line}, column {column:?}: count {value} is negative", path.display())]
+    NegativeCount {
+        path: PathBuf,
+        line: u64,
+        column: String,
+        value: String,
+    },
+    #[error("{}: line {line}, column {column:?}: count {value} is not a whole number", path.display())]
+    FractionalCount {
+        path: PathBuf,
+        line: u64,
+        column: String,
+        value: String,
+    },
+    #[error("{}: line {line} has {found} fields but the header has {expected}", path.display())]
+    RaggedRow {
+        path: PathBuf,
+        line: u64,
+        expected: usize,
+        found: usize,
+    },
+    #[error("{}: matrix has {rows} rows but {columns} columns", path.display())]
+    NotSquare {
+        path: PathBuf,
+        rows: usize,
+        columns: usize,
+    },
+    #[error("{}: line {line}: area code {code:?} already appeared on line {first_line}", path.display())]
+    DuplicateCode {
+        path: PathBuf,
+        line: u64,
+        first_line: u64,
+        code: String,
+    },
+    #[error("{}: column {column} repeats the area code {code:?}", path.display())]
+    DuplicateColumn {
+        path: PathBuf,
+        column: usize,
+        code: String,
+    },
+    #[error(
+        "{}: row and column codes differ; only in rows: {}; only in columns: {}",
+        path.display(),
+        summarise_codes(only_in_rows),
+        summarise_codes(only_in_columns)
+    )]
+    CodeSetMismatch {
+        path: PathBuf,
+        only_in_rows: Vec<String>,
+        only_in_columns: Vec<String>,
+    },
+}
+
+/// Lists the first few codes of a mismatch so that the message stays readable
+fn summarise_codes(codes: &[String]) -> String {
+    const SHOWN: usize = 5;
+    if codes.is_empty() {
+        return "none".to_owned();
+    }
+    let mut summary = codes
+        .iter()
+        .take(SHOWN)
+        .map(|code| format!("{:?}", code))
+        .collect::<Vec<String>>()
+        .join(", ");
+    if codes.len() > SHOWN {
+        summary.push_str(&format!(" and {} more", codes.len() - SHOWN));
+    }
+    summary
+}
+
+/// Reads a square flow matrix where the first column holds the origin codes and the header row
+/// holds the destination codes. Columns are reordered to match the rows if necessary, so the
+/// header may list the codes in any order as long as it contains exactly the row codes.
+///
+/// Nodes are numbered in order of their codes rather than their position in the file.
+pub fn read_adjacency_matrix_to_graph(path: &Path) -> Result<(Vec<String>, Graph), MatrixError> {
+    let csv_error = |source| MatrixError::Csv {
+        path: path.to_owned(),
+        source,
+    };
+    let input = open_input(path).map_err(|error| csv_error(error.into()))?;
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+    let headers = reader.headers().map_err(csv_error)?.clone();
 
-pub fn read_adjacency_matrix_to_graph(path: &Path) -> Result<(Vec<String>, Graph)> {
     let mut adjacency_matrix = Vec::new();
-    let mut reader = csv::Reader::from_path(path)?;
     let mut codes = Vec::new();
+    let mut code_lines = FxHashMap::default();
     for result in reader.records() {
-        let record = result?;
-        let code = record.get(0).unwrap().to_owned();
+        let record = result.map_err(csv_error)?;
+        let line = record.position().map_or(0, |position| position.line());
+        if record.len() != headers.len() {
+            return Err(MatrixError::RaggedRow {
+                path: path.to_owned(),
+                line,
+                expected: headers.len(),
+                found: record.len(),
+            });
+        }
+
+        let code = record.get(0).unwrap_or_default().to_owned();
+        if let Some(&first_line) = code_lines.get(&code) {
+            return Err(MatrixError::DuplicateCode {
+                path: path.to_owned(),
+                line,
+                first_line,
+                code,
+            });
+        }
+        code_lines.insert(code.clone(), line);
         codes.push(code);
-        let row: Result<Vec<u32>, _> = (1..record.len())
-            .map(|i| record.get(i).unwrap().parse::<u32>())
+
+        let row = (1..record.len())
+            .map(|i| parse_count(path, line, &headers[i], &record[i]))
+            .collect::<Result<Vec<u32>, MatrixError>>()?;
+        adjacency_matrix.push(row);
+    }
+
+    if codes.len() != headers.len().saturating_sub(1) {
+        return Err(MatrixError::NotSquare {
+            path: path.to_owned(),
+            rows: codes.len(),
+            columns: headers.len().saturating_sub(1),
+        });
+    }
+
+    let code_to_row = codes
+        .iter()
+        .enumerate()
+        .map(|(row, code)| (code.as_str(), row))
+        .collect::<FxHashMap<&str, usize>>();
+    let mut column_to_row = Vec::with_capacity(codes.len());
+    let mut only_in_columns = Vec::new();
+    let mut seen_columns = FxHashMap::default();
+    for (column, header) in headers.iter().enumerate().skip(1) {
+        if seen_columns.insert(header, column).is_some() {
+            return Err(MatrixError::DuplicateColumn {
+                path: path.to_owned(),
+                column,
+                code: header.to_owned(),
+            });
+        }
+        match code_to_row.get(header) {
+            Some(&row) => column_to_row.push(row),
+            None => only_in_columns.push(header.to_owned()),
+        }
+    }
+    if !only_in_columns.is_empty() {
+        let only_in_rows = codes
+            .iter()
+            .filter(|code| !seen_columns.contains_key(code.as_str()))
+            .cloned()
             .collect();
-        adjacency_matrix.push(row?);
+        return Err(MatrixError::CodeSetMismatch {
+            path: path.to_owned(),
+            only_in_rows,
+            only_in_columns,
+        });
+    }
+
+    // Order nodes by code, so that results do not depend on the order of the rows in the file,
+    // and put each column in the position of the row with the same code
+    let mut order = (0..codes.len()).collect::<Vec<usize>>();
+    order.sort_by(|&a, &b| codes[a].cmp(&codes[b]));
+    let mut rank = vec![0; codes.len()];
+    for (position, &row) in order.iter().enumerate() {
+        rank[row] = position;
     }
-    Ok((codes, Graph::from_adjacency_matrix(adjacency_matrix)))
+    let column_positions = column_to_row
+        .iter()
+        .map(|&row| rank[row])
+        .collect::<Vec<usize>>();
+    let adjacency_matrix = order
+        .iter()
+        .map(|&row| {
+            let values = std::mem::take(&mut adjacency_matrix[row]);
+            let mut reordered = vec![0; values.len()];
+            for (&value, &position) in values.iter().zip(column_positions.iter()) {
+                reordered[position] = value;
+            }
+            reordered
+        })
+        .collect::<Vec<Vec<u32>>>();
+    let codes = order
+        .iter()
+        .map(|&row| codes[row].clone())
+        .collect::<Vec<String>>();
+
+    let graph = Graph::from_adjacency_matrix(adjacency_matrix)
+        .expect("matrix shape was checked while reading");
+    Ok((codes, graph))
+}
+
+fn parse_count(path: &Path, line: u64, column: &str, value: &str) -> Result<u32, MatrixError> {
+    let trimmed = value.trim();
+    if let Ok(count) = trimmed.parse::<u32>() {
+        return Ok(count);
+    }
+    // "NaN", "inf" and "-inf" parse as floats but are not counts of any kind
+    let number = trimmed
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite());
+    let error = match number {
+        // Whole numbers written with a decimal point, such as "12.0", are accepted
+        Some(number) if number.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&number) => {
+            return Ok(number as u32);
+        }
+        Some(number) if number < 0.0 => MatrixError::NegativeCount {
+            path: path.to_owned(),
+            line,
+            column: column.to_owned(),
+            value: value.to_owned(),
+        },
+        Some(number) if number.fract() != 0.0 => MatrixError::FractionalCount {
+            path: path.to_owned(),
+            line,
+            column: column.to_owned(),
+            value: value.to_owned(),
+        },
+        _ => MatrixError::InvalidCount {
+            path: path.to_owned(),
+            line,
+            column: column.to_owned(),
+            value: value.to_owned(),
+        },
+    };
+    Err(error)
+}
+
+/// Column names and options for reading long-format `origin,destination,count` flow tables
+#[derive(Clone, Debug)]
+pub struct EdgeListOptions {
+    pub origin_column: String,
+    pub destination_column: String,
+    pub count_column: String,
+    /// Add origins that never appear as a destination as nodes instead of failing
+    pub include_unknown_origins: bool,
+}
+
+impl Default for EdgeListOptions {
+    fn default() -> Self {
+        EdgeListOptions {
+            origin_column: "origin".to_owned(),
+            destination_column: "destination".to_owned(),
+            count_column: "count".to_owned(),
+            include_unknown_origins: false,
+        }
+    }
+}
+
+/// Reads a long-format flow table with one row per origin/destination pair.
+///
+/// The nodes are the destination codes, numbered in order of their codes. Pairs that do not
+/// appear have no flow, and pairs that appear more than once have their counts summed.
+pub fn read_edge_list_to_graph(
+    path: &Path,
+    options: &EdgeListOptions,
+) -> Result<(Vec<String>, Graph)> {
+    let mut reader = csv::Reader::from_reader(open_input(path)?);
+    let headers = reader.headers()?.clone();
+    let column_index = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| anyhow!("{} has no column named {:?}", path.display(), name))
+    };
+    let origin_column = column_index(&options.origin_column)?;
+    let destination_column = column_index(&options.destination_column)?;
+    let count_column = column_index(&options.count_column)?;
+
+    let mut flows = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+        let line = record.position().map_or(0, |position| position.line());
+        let field = |column: usize| record.get(column).unwrap_or_default();
+        let count = parse_count(path, line, &options.count_column, field(count_column))?;
+        flows.push((
+            line,
+            field(origin_column).to_owned(),
+            field(destination_column).to_owned(),
+            count,
+        ));
+    }
+
+    let mut codes = Vec::new();
+    let mut code_to_node = FxHashMap::default();
+    for (_, _, destination, _) in flows.iter() {
+        if !code_to_node.contains_key(destination) {
+            code_to_node.insert(destination.clone(), codes.len());
+            codes.push(destination.clone());
+        }
+    }
+    for (line, origin, _, _) in flows.iter() {
+        if code_to_node.contains_key(origin) {
+            continue;
+        }
+        if !options.include_unknown_origins {
+            bail!(
+                "{} line {}: origin {:?} does not appear as a destination",
+                path.display(),
+                line,
+                origin
+            );
+        }
+        code_to_node.insert(origin.clone(), codes.len());
+        codes.push(origin.clone());
+    }
+
+    // Number nodes in order of their codes rather than their first appearance in the file
+    codes.sort();
+    for (node, code) in codes.iter().enumerate() {
+        code_to_node.insert(code.clone(), node);
+    }
+
+    let edges = flows
+        .iter()
+        .map(|(_, origin, destination, count)| Edge {
+            source: code_to_node[origin],
+            target: code_to_node[destination],
+            weight: *count,
+        })
+        .collect::<Vec<Edge>>();
+
+    let graph = Graph::from_edges(codes.len(), edges);
+    Ok((codes, graph))
+}
+
+/// Reads spatial adjacency from a CSV with a header row whose first two columns hold the codes of
+/// two adjacent areas. Every code must be one of `codes`.
+pub fn read_spatial_adjacency(path: &Path, codes: &[String]) -> Result<SpatialAdjacency> {
+    let code_to_node = codes
+        .iter()
+        .enumerate()
+        .map(|(node, code)| (code.as_str(), node))
+        .collect::<FxHashMap<&str, usize>>();
+    let mut reader = csv::Reader::from_reader(open_input(path)?);
+    let mut pairs = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+        let line = record.position().map_or(0, |position| position.line());
+        let node = |column: usize| -> Result<usize> {
+            let code = record.get(column).ok_or_else(|| {
+                anyhow!("{} line {}: expected two area codes", path.display(), line)
+            })?;
+            code_to_node.get(code).copied().ok_or_else(|| {
+                anyhow!(
+                    "{} line {}: area code {:?} is not in the flow data",
+                    path.display(),
+                    line,
+                    code
+                )
+            })
+        };
+        pairs.push((node(0)?, node(1)?));
+    }
+    Ok(SpatialAdjacency::from_pairs(codes.len(), pairs))
+}
+
+/// Reads a node-to-area lookup such as the one written by [`write_nodes_to_areas`], taking the
+/// node code from the first column and the area label from the second. Further columns are
+/// ignored.
+pub fn read_lookup(path: &Path) -> Result<BTreeMap<String, String>> {
+    let mut reader = csv::Reader::from_reader(open_input(path)?);
+    let mut lookup = BTreeMap::new();
+    for result in reader.records() {
+        let record = result?;
+        let line = record.position().map_or(0, |position| position.line());
+        let (Some(code), Some(area)) = (record.get(0), record.get(1)) else {
+            bail!(
+                "{} line {}: expected a code and an area",
+                path.display(),
+                line
+            );
+        };
+        if lookup.insert(code.to_owned(), area.to_owned()).is_some() {
+            bail!(
+                "{} line {}: code {:?} appears twice",
+                path.display(),
+                line,
+                code
+            );
+        }
+    }
+    Ok(lookup)
+}
+
+/// Reads a lookup from area code to name, taken from the first two columns of a CSV with a
+/// header row
+pub fn read_names(path: &Path) -> Result<FxHashMap<String, String>> {
+    let mut reader = csv::Reader::from_reader(open_input(path)?);
+    let mut names = FxHashMap::default();
+    for result in reader.records() {
+        let record = result?;
+        match (record.get(0), record.get(1)) {
+            (Some(code), Some(name)) => {
+                names.insert(code.to_owned(), name.to_owned());
+            }
+            _ => bail!(
+                "{} line {}: expected a code and a name",
+                path.display(),
+                record.position().map_or(0, |position| position.line())
+            ),
+        }
+    }
+    Ok(names)
+}
+
+/// Writes a `code,area,name` lookup with one row per node, in node order
+pub fn write_lookup(
+    path: &Path,
+    codes: &[String],
+    partition: &Partition,
+    area_names: &[String],
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(Output::create(path)?);
+    writer.write_record(["code", "area", "name"])?;
+    for (code, &area) in codes.iter().zip(partition.node_to_area.iter()) {
+        writer.write_record([code, &area.to_string(), &area_names[area]])?;
+    }
+    finish_csv(writer)
 }
 
-#[allow(dead_code)]
 pub fn write_nodes_to_areas(
     path: &Path,
     codes: &[String],
     nodes: &[usize],
     areas: &[usize],
+    area_names: &[String],
     area_metadata: &HashMap<usize, [f64; 3]>,
+    unallocatable: &[bool],
 ) -> Result<()> {
-    let mut writer = csv::Writer::from_path(path)?;
+    let mut writer = csv::Writer::from_writer(Output::create(path)?);
     writer.write_record([
         "code",
         "area",
+        "name",
         "self_containment",
         "population",
         "workforce",
+        "unallocatable",
     ])?;
     for (node, area) in nodes.iter().zip(areas.iter()) {
         // Write the node to area mapping and the metadata for the area
@@ -41,10 +474,42 @@ pub fn write_nodes_to_areas(
         writer.write_record([
             codes[*node].as_str(),
             area.to_string().as_str(),
+            area_names[*area].as_str(),
             area_metadata[area][0].to_string().as_str(),
             area_metadata[area][1].to_string().as_str(),
             area_metadata[area][2].to_string().as_str(),
+            unallocatable[*area].to_string().as_str(),
         ])?;
     }
+    finish_csv(writer)
+}
+
+/// Writes one row per area, as JSON if `path` ends in `.json` and as CSV otherwise. Either can
+/// be compressed by adding `.gz` or `.zst`.
+pub fn write_area_summaries(path: &Path, summaries: &[AreaSummary]) -> Result<()> {
+    write_table(path, summaries)
+}
+
+/// Writes rows as a JSON array if `path` ends in `.json` and as CSV with a header otherwise.
+/// Either can be compressed by adding `.gz` or `.zst`.
+pub fn write_table<T: Serialize>(path: &Path, rows: &[T]) -> Result<()> {
+    let mut output = Output::create(path)?;
+    if inner_extension(path) == Some("json") {
+        serde_json::to_writer_pretty(&mut output, rows)?;
+        output.finish()?;
+        Ok(())
+    } else {
+        let mut writer = csv::Writer::from_writer(output);
+        for row in rows {
+            writer.serialize(row)?;
+        }
+        finish_csv(writer)
+    }
+}
+
+/// Flushes a CSV writer and finishes its compressed stream
+fn finish_csv(writer: csv::Writer<Output>) -> Result<()> {
+    let output = writer.into_inner().map_err(|error| error.into_error())?;
+    output.finish()?;
     Ok(())
 }
@@ -0,0 +1,47 @@
+//! Travel to work area (TTWA) detection from commuting flow matrices, following the ONS
+//! methodology.
+//!
+//! Read flows into a [`Graph`] with one of the readers in [`io`], choose [`Parameters`], and call
+//! [`travel_to_work_areas`] to get a [`Partition`] of the nodes into TTWAs.
+pub mod algorithm;
+pub mod area;
+pub mod bootstrap;
+pub mod compare;
+pub mod compression;
+pub mod contiguity;
+pub mod evaluate;
+pub mod graph;
+mod heap;
+pub mod inspect;
+pub mod io;
+pub mod naming;
+pub mod params;
+pub mod partition;
+pub mod refine;
+pub mod spatial;
+pub mod sweep;
+pub mod ttwa_naive;
+pub mod ttwa_v2;
+
+pub use algorithm::{
+    check_invariants, Drift, Limits, Options, OrphanPolicy, Progress, RegionalisationAlgorithm,
+    StopReason, TtwaResult, ZeroFlowPolicy,
+};
+pub use area::{Area, AreaStats, AreaSummary};
+pub use bootstrap::{bootstrap, node_stability, resample_flows, Replicate};
+pub use compare::{compare_partitions, BestMatch, Comparison, SwitchedNode};
+pub use contiguity::{eliminate_fragments, FragmentCleanup, FragmentMove};
+pub use evaluate::{evaluate_lookup, evaluate_partition, AreaEvaluation};
+pub use graph::Graph;
+pub use inspect::{summarise_flows, FlowSummary};
+pub use io::{
+    read_adjacency_matrix_to_graph, read_edge_list_to_graph, read_lookup, read_spatial_adjacency,
+    write_area_summaries, write_lookup, write_nodes_to_areas, write_table, EdgeListOptions,
+    MatrixError,
+};
+pub use params::Parameters;
+pub use partition::Partition;
+pub use refine::{refine_boundaries, NodeMove, Refinement};
+pub use spatial::{NonContiguousArea, SpatialAdjacency};
+pub use sweep::{sweep, SweepGrid, SweepRow};
+pub use ttwa_v2::travel_to_work_areas;
@@ -1,62 +1,38 @@
 use anyhow::Result;
-use std::path::Path;
-mod graph;
-mod io;
-// mod ttwa_naive;
-mod ttwa_v2;
-use clap::Parser;
-use std::collections::HashMap;
+use clap::{Parser, Subcommand};
 
-use crate::io::read_adjacency_matrix_to_graph;
+mod cli;
 
 #[derive(Parser)]
-struct Args {
-    input: String,
-    output: Option<String>,
-    max_iter: Option<usize>,
+#[command(about = "Travel to work areas from commuting flows")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let path = args.input;
-
-    let (codes, graph) = read_adjacency_matrix_to_graph(Path::new(&path))?;
-
-    let ttwas = ttwa_v2::travel_to_work_areas(&graph);
-    println!("Found {} TTWAs", ttwas.len());
-
-    let mut nodes = Vec::new();
-    let mut areas = Vec::new();
+#[derive(Subcommand)]
+enum Command {
+    /// Compute TTWAs from a flow file
+    Run(cli::run::RunArgs),
+    /// Summarise a flow file
+    Inspect(cli::inspect::InspectArgs),
+    /// Score an existing node-to-area lookup against a flow file
+    Evaluate(cli::evaluate::EvaluateArgs),
+    /// Compare two node-to-area lookups
+    Compare(cli::compare::CompareArgs),
+    /// Compute TTWAs for every combination of parameter values in a grid
+    Sweep(cli::sweep::SweepArgs),
+    /// Measure how stable the TTWAs are when the flows are resampled
+    Bootstrap(cli::bootstrap::BootstrapArgs),
+}
 
-    // Print the results
-    for (area_id, area) in ttwas.iter().enumerate() {
-        for node in area.nodes.iter() {
-            nodes.push(*node);
-            areas.push(area_id);
-        }
-        println!(
-            "Area {} has {} self containment, {} population, {} workforce",
-            area_id, area.self_containment, area.flow_from_area, area.flow_to_area
-        );
-    }
-    let area_metadata = ttwas
-        .iter()
-        .enumerate()
-        .map(|(area_id, area)| {
-            (
-                area_id,
-                [
-                    area.self_containment,
-                    area.flow_from_area,
-                    area.flow_to_area,
-                ],
-            )
-        })
-        .collect::<HashMap<usize, [f64; 3]>>();
-    // Write the results to a file
-    if let Some(output) = args.output {
-        io::write_nodes_to_areas(Path::new(&output), &codes, &nodes, &areas, &area_metadata)?;
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Run(args) => cli::run::run(args),
+        Command::Inspect(args) => cli::inspect::inspect(args),
+        Command::Evaluate(args) => cli::evaluate::evaluate(args),
+        Command::Compare(args) => cli::compare::compare(args),
+        Command::Sweep(args) => cli::sweep::sweep_parameters(args),
+        Command::Bootstrap(args) => cli::bootstrap::bootstrap_flows(args),
     }
-
-    Ok(())
 }
@@ -0,0 +1,74 @@
+use rustc_hash::FxHashMap;
+use std::cmp::Reverse;
+
+use crate::area::Area;
+use crate::graph::Graph;
+use crate::partition::Partition;
+
+/// A second workplace with at least this share of the largest workplace's jobs is named
+/// alongside it, giving "X & Y" names
+const JOINT_NAME_SHARE: f64 = 0.8;
+
+/// Orders areas by the code of their dominant workplace and names each one after it.
+///
+/// The dominant workplace is the node with the most jobs (`Node::in_degree`), so the index of
+/// an area in the returned partition is a stable id: it does not depend on the order in which the
+/// algorithm produced the areas. Node names come from `names`, falling back to the node's code.
+/// An area is named "X & Y" when its second largest workplace has at least
+/// `JOINT_NAME_SHARE` of the jobs of the largest. Areas that would share a name have the code of
+/// their dominant workplace appended.
+pub fn sort_and_name_areas(
+    partition: Partition,
+    graph: &Graph,
+    codes: &[String],
+    names: Option<&FxHashMap<String, String>>,
+) -> (Partition, Vec<String>) {
+    let node_name = |node: usize| {
+        names
+            .and_then(|names| names.get(&codes[node]))
+            .unwrap_or(&codes[node])
+            .clone()
+    };
+
+    let mut named = partition
+        .areas
+        .into_iter()
+        .map(|area| {
+            // Largest workplaces first, ties broken by code
+            let mut workplaces = area.nodes.iter().copied().collect::<Vec<usize>>();
+            workplaces.sort_by_key(|&node| (Reverse(graph.nodes[node].in_degree), &codes[node]));
+
+            let dominant = workplaces[0];
+            let mut name = node_name(dominant);
+            if let Some(&second) = workplaces.get(1) {
+                let largest = graph.nodes[dominant].in_degree as f64;
+                let second_name = node_name(second);
+                if graph.nodes[second].in_degree as f64 >= JOINT_NAME_SHARE * largest
+                    && second_name != name
+                {
+                    name = format!("{} & {}", name, second_name);
+                }
+            }
+            (codes[dominant].clone(), name, area)
+        })
+        .collect::<Vec<(String, String, Area)>>();
+    named.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut name_counts = FxHashMap::default();
+    for (_, name, _) in named.iter() {
+        *name_counts.entry(name.clone()).or_insert(0) += 1;
+    }
+
+    let (areas, names): (Vec<Area>, Vec<String>) = named
+        .into_iter()
+        .map(|(dominant_code, name, area)| {
+            let name = if name_counts[&name] > 1 {
+                format!("{} ({})", name, dominant_code)
+            } else {
+                name
+            };
+            (area, name)
+        })
+        .unzip();
+    (Partition::from_areas(areas, graph.nodes.len()), names)
+}
@@ -0,0 +1,125 @@
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::compression::is_stdio;
+
+/// Size and self-containment criteria used by the x-equation.
+///
+/// The defaults are the values used by the ONS for the 2011 TTWAs. Size is measured in resident
+/// workers and containment as the smaller of supply-side and demand-side self-containment.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Parameters {
+    /// Size at or above which an area only needs to meet `min_containment`
+    pub target_size: f64,
+    /// Smallest size an area can have and still be accepted
+    pub min_size: f64,
+    /// Containment at or above which an area only needs to meet `min_size`
+    pub target_containment: f64,
+    /// Smallest containment an area can have and still be accepted
+    pub min_containment: f64,
+    /// The algorithm stops once the worst x-equation score reaches this value
+    pub threshold: f64,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters {
+            target_size: 25000.0,
+            min_size: 3500.0,
+            target_containment: 0.75,
+            min_containment: 0.667,
+            threshold: 0.0,
+        }
+    }
+}
+
+impl Parameters {
+    /// Reads parameters from a TOML or JSON file, chosen by the file extension. `-` reads from
+    /// standard input, as JSON if it starts with `{` and TOML otherwise.
+    /// Fields missing from the file keep their default values.
+    pub fn from_file(path: &Path) -> Result<Parameters> {
+        read_config(path)
+    }
+
+    /// Rejects combinations for which the x-equation is undefined or meaningless.
+    pub fn validate(&self) -> Result<()> {
+        let fields = [
+            ("target_size", self.target_size),
+            ("min_size", self.min_size),
+            ("target_containment", self.target_containment),
+            ("min_containment", self.min_containment),
+            ("threshold", self.threshold),
+        ];
+        for (name, value) in fields {
+            if !value.is_finite() {
+                bail!("{} must be a finite number, got {}", name, value);
+            }
+        }
+        if self.min_size < 0.0 {
+            bail!("min_size must not be negative, got {}", self.min_size);
+        }
+        if self.min_size >= self.target_size {
+            bail!(
+                "min_size ({}) must be smaller than target_size ({})",
+                self.min_size,
+                self.target_size
+            );
+        }
+        if self.min_containment <= 0.0 || self.target_containment > 1.0 {
+            bail!(
+                "containment thresholds must lie in (0, 1], got min_containment {} and target_containment {}",
+                self.min_containment,
+                self.target_containment
+            );
+        }
+        if self.min_containment >= self.target_containment {
+            bail!(
+                "min_containment ({}) must be smaller than target_containment ({})",
+                self.min_containment,
+                self.target_containment
+            );
+        }
+        Ok(())
+    }
+
+    /// Slope of the x-equation's indifference curve between the minimum and target values
+    pub fn tradeoff(&self) -> f64 {
+        (self.min_containment - self.target_containment) / (self.target_size - self.min_size)
+    }
+
+    /// Containment at which the indifference curve would meet zero size
+    pub fn intercept(&self) -> f64 {
+        self.target_containment - self.tradeoff() * self.min_size
+    }
+}
+
+/// Reads a TOML or JSON config file, chosen by the file extension. `-` reads from standard input,
+/// as JSON if it starts with `{` and TOML otherwise.
+pub(crate) fn read_config<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents = if is_stdio(path) {
+        std::io::read_to_string(std::io::stdin()).context("failed to read config from stdin")?
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?
+    };
+    // Standard input has no extension, but a JSON config is always an object
+    let format = match path.extension().and_then(|ext| ext.to_str()) {
+        None if is_stdio(path) && contents.trim_start().starts_with('{') => Some("json"),
+        None if is_stdio(path) => Some("toml"),
+        format => format,
+    };
+    let config = match format {
+        Some("toml") => toml::from_str(&contents)
+            .with_context(|| format!("failed to parse TOML config {}", path.display()))?,
+        Some("json") => serde_json::from_str(&contents)
+            .with_context(|| format!("failed to parse JSON config {}", path.display()))?,
+        _ => bail!(
+            "config file {} must have a .toml or .json extension",
+            path.display()
+        ),
+    };
+    Ok(config)
+}
@@ -0,0 +1,120 @@
+use anyhow::{bail, Result};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::BTreeMap;
+
+use crate::area::Area;
+use crate::graph::Graph;
+
+/// An assignment of every node of a graph to one of a set of non-empty areas
+#[derive(Clone, Debug, Default)]
+pub struct Partition {
+    pub areas: Vec<Area>,
+    /// Index into `areas` of the area each node belongs to
+    pub node_to_area: Vec<usize>,
+}
+
+impl Partition {
+    /// Builds a partition of `node_count` nodes from areas, dropping any that are empty.
+    /// Every node must belong to exactly one of the areas.
+    pub fn from_areas(areas: impl IntoIterator<Item = Area>, node_count: usize) -> Partition {
+        let areas = areas
+            .into_iter()
+            .filter(|area| !area.is_empty())
+            .collect::<Vec<Area>>();
+        let mut node_to_area = vec![usize::MAX; node_count];
+        for (area_index, area) in areas.iter().enumerate() {
+            for &node in area.nodes.iter() {
+                assert_eq!(
+                    node_to_area[node],
+                    usize::MAX,
+                    "node {} is in more than one area",
+                    node
+                );
+                node_to_area[node] = area_index;
+            }
+        }
+        assert!(
+            node_to_area.iter().all(|&area| area != usize::MAX),
+            "every node must belong to an area"
+        );
+        Partition {
+            areas,
+            node_to_area,
+        }
+    }
+
+    /// Builds a partition from a lookup of node code to area label, returning the label of each
+    /// area alongside it. `codes` gives the code of each node of `graph`, and the lookup must
+    /// cover exactly those codes.
+    ///
+    /// Areas are ordered by label, numerically if every label is a whole number.
+    pub fn from_lookup(
+        lookup: &BTreeMap<String, String>,
+        codes: &[String],
+        graph: &Graph,
+    ) -> Result<(Partition, Vec<String>)> {
+        let missing = codes
+            .iter()
+            .filter(|code| !lookup.contains_key(*code))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!(
+                "{} nodes are not in the lookup, such as {:?}",
+                missing.len(),
+                missing[0]
+            );
+        }
+        if lookup.len() > codes.len() {
+            let known = codes.iter().collect::<FxHashSet<_>>();
+            let unknown = lookup.keys().find(|code| !known.contains(code));
+            bail!(
+                "{} codes in the lookup are not in the flow data, such as {:?}",
+                lookup.len() - codes.len(),
+                unknown.map_or("", |code| code.as_str())
+            );
+        }
+
+        let mut labels = lookup.values().cloned().collect::<Vec<String>>();
+        labels.sort();
+        labels.dedup();
+        if labels.iter().all(|label| label.parse::<u64>().is_ok()) {
+            labels.sort_by_key(|label| label.parse::<u64>().unwrap());
+        }
+        let label_index = labels
+            .iter()
+            .enumerate()
+            .map(|(index, label)| (label.as_str(), index))
+            .collect::<FxHashMap<&str, usize>>();
+        let mut areas = vec![Area::default(); labels.len()];
+        for (node, code) in codes.iter().enumerate() {
+            areas[label_index[lookup[code].as_str()]].add_node(node, graph);
+        }
+        Ok((Partition::from_areas(areas, codes.len()), labels))
+    }
+
+    /// Number of areas
+    pub fn len(&self) -> usize {
+        self.areas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.areas.is_empty()
+    }
+
+    /// The area containing `node`
+    pub fn area_of(&self, node: usize) -> &Area {
+        &self.areas[self.node_to_area[node]]
+    }
+
+    /// The nodes of each area, sorted by index
+    pub fn sorted_nodes(&self) -> Vec<Vec<usize>> {
+        self.areas
+            .iter()
+            .map(|area| {
+                let mut nodes = area.nodes.iter().copied().collect::<Vec<usize>>();
+                nodes.sort();
+                nodes
+            })
+            .collect()
+    }
+}
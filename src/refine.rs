@@ -0,0 +1,159 @@
+use crate::area::tij2;
+use crate::graph::Graph;
+use crate::params::Parameters;
+use crate::partition::Partition;
+use crate::spatial::SpatialAdjacency;
+
+/// A node moved by [`refine_boundaries`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeMove {
+    pub pass: usize,
+    pub node: usize,
+    pub from: usize,
+    pub to: usize,
+    /// tij2 of the node against the two areas, each without the node
+    pub from_tij2: f64,
+    pub to_tij2: f64,
+    /// Increase in the total flow that stays within an area
+    pub self_containment_gain: u64,
+}
+
+pub struct Refinement {
+    pub partition: Partition,
+    pub moves: Vec<NodeMove>,
+    /// Number of passes over the nodes, including the last one if it made no moves
+    pub passes: usize,
+}
+
+/// Revisits the assignments made by the dissolution loop, moving nodes on the boundary of their
+/// area to a neighbouring area they fit better.
+///
+/// Each pass visits the nodes in index order. A node moves to the neighbouring area with the
+/// highest tij2 that beats its own area's, where both areas are scored without the node, if after
+/// the move both areas still meet `params.threshold` and the total internal flow of all areas
+/// goes up. Nodes are never moved out of or into unallocatable areas or out of single-node areas.
+/// Since every move raises the total internal flow, this stops once a pass makes no moves or
+/// after `max_passes` passes.
+///
+/// Neighbouring areas are those of the node's commuting neighbours. With `adjacency` they are the
+/// spatially adjacent areas instead, and moves that would split the node's area are skipped.
+pub fn refine_boundaries(
+    mut partition: Partition,
+    graph: &Graph,
+    params: &Parameters,
+    adjacency: Option<&SpatialAdjacency>,
+    max_passes: usize,
+) -> Refinement {
+    let mut moves = Vec::new();
+    let mut passes = 0;
+
+    while passes < max_passes {
+        passes += 1;
+        let moves_before = moves.len();
+        for node in 0..graph.nodes.len() {
+            if let Some(node_move) = try_move(&mut partition, graph, params, adjacency, node) {
+                moves.push(NodeMove {
+                    pass: passes,
+                    ..node_move
+                });
+            }
+        }
+        if moves.len() == moves_before {
+            break;
+        }
+    }
+
+    Refinement {
+        partition,
+        moves,
+        passes,
+    }
+}
+
+/// Moves `node` if a neighbouring area passes every check, returning the move
+fn try_move(
+    partition: &mut Partition,
+    graph: &Graph,
+    params: &Parameters,
+    adjacency: Option<&SpatialAdjacency>,
+    node: usize,
+) -> Option<NodeMove> {
+    let from = partition.node_to_area[node];
+    if partition.areas[from].unallocatable || partition.areas[from].nodes.len() == 1 {
+        return None;
+    }
+    let mut candidates = match adjacency {
+        Some(adjacency) => adjacency
+            .neighbours(node)
+            .iter()
+            .map(|&neighbour| partition.node_to_area[neighbour])
+            .collect::<Vec<usize>>(),
+        None => graph
+            .get_neighbors(node)
+            .map(|neighbour| partition.node_to_area[neighbour])
+            .collect(),
+    };
+    candidates.retain(|&area| area != from && !partition.areas[area].unallocatable);
+    if candidates.is_empty() {
+        return None;
+    }
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    // Score every area without the node, as the dissolution loop does
+    let internal_before = partition.areas[from].self_containment;
+    partition.areas[from].remove_node(node, graph);
+    partition.node_to_area[node] = usize::MAX;
+    let internal_lost = internal_before - partition.areas[from].self_containment;
+    let from_tij2 = tij2(graph, node, &partition.areas, from, &partition.node_to_area);
+
+    let mut better = candidates
+        .into_iter()
+        .map(|area| {
+            let to_tij2 = tij2(graph, node, &partition.areas, area, &partition.node_to_area);
+            (area, to_tij2)
+        })
+        .filter(|&(_, to_tij2)| to_tij2 > from_tij2)
+        .collect::<Vec<(usize, f64)>>();
+    // Highest tij2 first, ties going to the lowest index
+    better.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let from_is_valid = partition.areas[from].x_equation(params) >= params.threshold;
+    let from_stays_connected = || {
+        adjacency.is_none_or(|adjacency| {
+            let before = adjacency
+                .fragments(partition.areas[from].nodes.iter().copied().chain([node]))
+                .len();
+            adjacency
+                .fragments(partition.areas[from].nodes.iter().copied())
+                .len()
+                <= before
+        })
+    };
+    if from_is_valid && from_stays_connected() {
+        for (to, to_tij2) in better {
+            let internal_before = partition.areas[to].self_containment;
+            partition.areas[to].add_node(node, graph);
+            let internal_gained = partition.areas[to].self_containment - internal_before;
+            if internal_gained > internal_lost
+                && partition.areas[to].x_equation(params) >= params.threshold
+            {
+                partition.node_to_area[node] = to;
+                return Some(NodeMove {
+                    pass: 0,
+                    node,
+                    from,
+                    to,
+                    from_tij2,
+                    to_tij2,
+                    self_containment_gain: internal_gained - internal_lost,
+                });
+            }
+            partition.areas[to].remove_node(node, graph);
+        }
+    }
+
+    partition.areas[from].add_node(node, graph);
+    partition.node_to_area[node] = from;
+    None
+}
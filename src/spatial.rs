@@ -0,0 +1,91 @@
+use crate::partition::Partition;
+
+/// An area whose nodes do not form one spatially connected piece
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NonContiguousArea {
+    /// Index of the area in the partition
+    pub area: usize,
+    /// The connected pieces of the area, largest first and then by lowest node, each sorted by
+    /// node index
+    pub fragments: Vec<Vec<usize>>,
+}
+
+/// Which nodes share a boundary, as read from a list of polygon adjacency pairs.
+/// Adjacency is symmetric and a node is never adjacent to itself.
+#[derive(Clone, Debug, Default)]
+pub struct SpatialAdjacency {
+    neighbours: Vec<Vec<usize>>,
+}
+
+impl SpatialAdjacency {
+    /// Builds the adjacency of `node_count` nodes from pairs of node indices, in either order
+    pub fn from_pairs(node_count: usize, pairs: impl IntoIterator<Item = (usize, usize)>) -> Self {
+        let mut neighbours = vec![Vec::new(); node_count];
+        for (a, b) in pairs {
+            if a != b {
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+        }
+        for list in neighbours.iter_mut() {
+            list.sort_unstable();
+            list.dedup();
+        }
+        SpatialAdjacency { neighbours }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.neighbours.len()
+    }
+
+    /// The nodes adjacent to `node`, sorted by index
+    pub fn neighbours(&self, node: usize) -> &[usize] {
+        &self.neighbours[node]
+    }
+
+    /// Splits `nodes` into groups that are connected through adjacencies within the set, largest
+    /// first and then by lowest node. Each group is sorted by node index.
+    pub fn fragments(&self, nodes: impl IntoIterator<Item = usize>) -> Vec<Vec<usize>> {
+        let mut members = nodes.into_iter().collect::<Vec<usize>>();
+        members.sort_unstable();
+        let mut seen = vec![false; members.len()];
+        let mut fragments = Vec::new();
+        for start in 0..members.len() {
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut fragment = vec![members[start]];
+            let mut next = 0;
+            while next < fragment.len() {
+                for &neighbour in self.neighbours(fragment[next]) {
+                    let Ok(position) = members.binary_search(&neighbour) else {
+                        continue;
+                    };
+                    if !seen[position] {
+                        seen[position] = true;
+                        fragment.push(neighbour);
+                    }
+                }
+                next += 1;
+            }
+            fragment.sort_unstable();
+            fragments.push(fragment);
+        }
+        fragments.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+        fragments
+    }
+
+    /// The areas of `partition` that are split into more than one spatially connected piece
+    pub fn non_contiguous_areas(&self, partition: &Partition) -> Vec<NonContiguousArea> {
+        partition
+            .areas
+            .iter()
+            .enumerate()
+            .filter_map(|(area, nodes)| {
+                let fragments = self.fragments(nodes.nodes.iter().copied());
+                (fragments.len() > 1).then_some(NonContiguousArea { area, fragments })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::algorithm::{Options, TtwaResult};
+use crate::graph::Graph;
+use crate::params::{read_config, Parameters};
+use crate::ttwa_v2::travel_to_work_areas;
+
+/// Values to try for each parameter. A parameter missing from the grid file keeps its default.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SweepGrid {
+    pub target_size: Vec<f64>,
+    pub min_size: Vec<f64>,
+    pub target_containment: Vec<f64>,
+    pub min_containment: Vec<f64>,
+    pub threshold: Vec<f64>,
+}
+
+impl Default for SweepGrid {
+    fn default() -> Self {
+        let params = Parameters::default();
+        SweepGrid {
+            target_size: vec![params.target_size],
+            min_size: vec![params.min_size],
+            target_containment: vec![params.target_containment],
+            min_containment: vec![params.min_containment],
+            threshold: vec![params.threshold],
+        }
+    }
+}
+
+impl SweepGrid {
+    /// Reads a grid from a TOML or JSON file, chosen by the file extension
+    pub fn from_file(path: &Path) -> Result<SweepGrid> {
+        read_config(path)
+    }
+
+    /// Every combination of the grid's values, varying `threshold` fastest and `target_size`
+    /// slowest. Combinations are not validated.
+    pub fn parameter_sets(&self) -> Vec<Parameters> {
+        let mut sets = Vec::new();
+        for &target_size in self.target_size.iter() {
+            for &min_size in self.min_size.iter() {
+                for &target_containment in self.target_containment.iter() {
+                    for &min_containment in self.min_containment.iter() {
+                        for &threshold in self.threshold.iter() {
+                            sets.push(Parameters {
+                                target_size,
+                                min_size,
+                                target_containment,
+                                min_containment,
+                                threshold,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        sets
+    }
+}
+
+/// One row of the sweep results table
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SweepRow {
+    /// Position of the parameter set in the sweep
+    pub run: usize,
+    pub target_size: f64,
+    pub min_size: f64,
+    pub target_containment: f64,
+    pub min_containment: f64,
+    pub threshold: f64,
+    pub stop_reason: String,
+    pub iterations: usize,
+    pub areas: usize,
+    /// Smallest self-containment of any area, taking the lower of supply and demand
+    pub min_self_containment: f64,
+    pub median_self_containment: f64,
+    /// Share of resident workers living in areas below the x-equation threshold
+    pub failing_worker_share: f64,
+}
+
+impl SweepRow {
+    pub fn new(run: usize, params: &Parameters, result: &TtwaResult) -> SweepRow {
+        let areas = &result.partition.areas;
+        let mut containment = areas
+            .iter()
+            .map(|area| {
+                area.supply_self_containment()
+                    .min(area.demand_self_containment())
+            })
+            .collect::<Vec<f64>>();
+        containment.sort_by(f64::total_cmp);
+        let median_self_containment = match containment.len() {
+            0 => 0.0,
+            n if n % 2 == 0 => (containment[n / 2 - 1] + containment[n / 2]) / 2.0,
+            n => containment[n / 2],
+        };
+        let workers = areas.iter().map(|area| area.flow_from_area).sum::<u64>();
+        let failing_workers = areas
+            .iter()
+            .filter(|area| area.x_equation(params) < params.threshold)
+            .map(|area| area.flow_from_area)
+            .sum::<u64>();
+
+        SweepRow {
+            run,
+            target_size: params.target_size,
+            min_size: params.min_size,
+            target_containment: params.target_containment,
+            min_containment: params.min_containment,
+            threshold: params.threshold,
+            stop_reason: result.stop_reason.to_string(),
+            iterations: result.iterations,
+            areas: areas.len(),
+            min_self_containment: containment.first().copied().unwrap_or(0.0),
+            median_self_containment,
+            failing_worker_share: if workers == 0 {
+                0.0
+            } else {
+                failing_workers as f64 / workers as f64
+            },
+        }
+    }
+}
+
+/// Runs [`travel_to_work_areas`] on the same graph once for each parameter set, returning the
+/// results in the order of the sets.
+///
+/// The runs are independent and are spread over the current rayon thread pool; the results are
+/// the same for any number of threads.
+pub fn sweep(graph: &Graph, parameter_sets: &[Parameters], options: &Options) -> Vec<TtwaResult> {
+    parameter_sets
+        .par_iter()
+        .map(|params| travel_to_work_areas(graph, params, options))
+        .collect()
+}
@@ -1,201 +1,250 @@
 use rustc_hash::FxHashSet;
-
-use crate::graph::{EdgeDirection, Graph};
-const TARGET_SIZE: f64 = 25000.0;
-const MIN_SIZE: f64 = 3500.0;
-const TARGET_CONTAINMENT: f64 = 0.75;
-const MIN_CONTAINMENT: f64 = 0.667;
-const TRADEOFF: f64 = (MIN_CONTAINMENT - TARGET_CONTAINMENT) / (TARGET_SIZE - MIN_SIZE);
-const INTERCEPT: f64 = TARGET_CONTAINMENT - TRADEOFF * MIN_SIZE;
-const THRESHOLD: f64 = 0.0;
+use std::cmp::Reverse;
+use std::time::Instant;
+
+use crate::algorithm::{
+    check_invariants, initial_areas, report_progress, stop_reason, InitialAreas, Options,
+    OrphanPolicy, RegionalisationAlgorithm, TtwaResult,
+};
+use crate::area::{tij2, Area};
+use crate::graph::Graph;
+use crate::heap::IndexedMinHeap;
+use crate::params::Parameters;
+use crate::partition::Partition;
 
 type NodeIndex = usize;
 
-#[derive(Clone)]
-pub struct Area {
-    pub nodes: FxHashSet<NodeIndex>,
-    pub flow_to_area: f64,
-    pub flow_from_area: f64,
-    pub self_containment: f64,
+/// The neighbour-based engine implemented by [`travel_to_work_areas`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct V2;
+
+impl RegionalisationAlgorithm for V2 {
+    fn name(&self) -> &'static str {
+        "v2"
+    }
+
+    fn run(&self, graph: &Graph, params: &Parameters, options: &Options) -> TtwaResult {
+        travel_to_work_areas(graph, params, options)
+    }
 }
-type TravelToWorkAreas = Vec<Area>;
-
-pub fn travel_to_work_areas(graph: &Graph) -> TravelToWorkAreas {
-    // Assign each node to an area
-    let mut node2area = Vec::new();
-    let mut areas = TravelToWorkAreas::new();
-    for node in graph.nodes.iter() {
-        let mut area = Area {
-            nodes: FxHashSet::default(),
-            flow_to_area: 0.0,
-            flow_from_area: 0.0,
-            self_containment: 0.0,
-        };
-        area.nodes.insert(node.id);
-        area.flow_to_area += node.in_degree as f64;
-        area.flow_from_area += node.out_degree as f64;
-        area.self_containment += graph
-            .get_edges(node.id, EdgeDirection::In)
-            .filter(|&e| e.source == node.id)
-            .map(|e| e.weight)
-            .sum::<u32>() as f64;
-
-        areas.push(area);
-        node2area.push(areas.len() - 1);
+
+/// Starts with every node in its own area and repeatedly dissolves the area with the worst
+/// x-equation score, moving each of its nodes to the neighbouring area with the highest tij2.
+///
+/// The result only depends on the graph, not on hashing or iteration order. Ties are broken by
+/// index: the worst area is the lowest-indexed one among equal scores, a dissolved area's nodes
+/// are reassigned in order of decreasing resident workers and then index, and a node joins the
+/// lowest-indexed area among those with equal tij2. Area indices start out as node indices, so
+/// the readers order nodes by code to make results independent of the input's row order.
+///
+/// Nodes with no neighbour in another area are handled by `Options::orphan_policy`. Those that
+/// cannot be placed form areas flagged `Area::unallocatable`, which are never dissolved and so do
+/// not count towards `TtwaResult::worst_score`. Other orphans join them in preference to starting
+/// a new one, so an island ends up as a single unallocatable area. The last area that is not
+/// unallocatable is never dissolved; if it fails the threshold the run stops with
+/// `StopReason::SingleArea`.
+pub fn travel_to_work_areas(graph: &Graph, params: &Parameters, options: &Options) -> TtwaResult {
+    let limits = &options.limits;
+    let start = Instant::now();
+    let InitialAreas {
+        mut areas,
+        mut node2area,
+        zero_flow_nodes,
+        excluded_nodes,
+    } = initial_areas(graph, options);
+    let mut drift = Vec::new();
+
+    // Areas keyed by x_equation so the worst one can be found without scanning every area.
+    // Only the areas that gain or lose nodes in an iteration need their score updated.
+    let mut queue = IndexedMinHeap::new(areas.len());
+    for (area_index, area) in areas.iter().enumerate() {
+        if !area.is_empty() && !area.unallocatable {
+            queue.push_or_update(area_index, area.x_equation(params));
+        }
     }
     let mut iter = 0;
 
     loop {
         assert_eq!(node2area.len(), graph.nodes.len());
         // Find worst x_equation
-        let mut worst_area = None;
-        let mut worst_x_equation = f64::INFINITY;
-
-        for (area_index, area) in areas
-            .iter()
-            .enumerate()
-            .filter(|(_, a)| !a.nodes.is_empty())
-        {
-            let x_equation = x_equation(area);
-            if x_equation < worst_x_equation {
-                worst_x_equation = x_equation;
-                worst_area = Some(area_index);
-            }
-        }
+        let (worst_area, worst_x_equation) = match queue.peek() {
+            Some((area_index, x_equation)) => (Some(area_index), x_equation),
+            None => (None, f64::INFINITY),
+        };
 
-        if iter % 1000 == 0 {
-            println!(
-                "Iteration {}: {}, {} areas remaining",
-                iter,
-                worst_x_equation,
-                areas.iter().filter(|a| !a.nodes.is_empty()).count()
-            );
-        }
+        report_progress(options, iter, worst_x_equation, queue.len());
 
-        if worst_x_equation >= THRESHOLD {
-            break;
+        if let Some(stop_reason) =
+            stop_reason(worst_x_equation, queue.len(), iter, start, params, limits)
+        {
+            // Empty areas are dropped by the partition
+            let partition = Partition::from_areas(areas, graph.nodes.len());
+            return TtwaResult {
+                partition,
+                stop_reason,
+                worst_score: worst_x_equation,
+                iterations: iter,
+                drift,
+                zero_flow_nodes,
+                excluded_nodes,
+            };
         }
 
         let worst_area = worst_area.unwrap();
-        let worst_area_nodes = areas[worst_area].nodes.clone();
+        // Reassign the largest nodes first, so that the order does not depend on how the
+        // area's node set happens to iterate. Ties are broken by node index.
+        let mut worst_area_nodes = areas[worst_area].nodes.iter().copied().collect::<Vec<_>>();
+        worst_area_nodes.sort_by_key(|&node| (Reverse(graph.nodes[node].out_degree), node));
+        queue.remove(worst_area);
+        let mut touched_areas = FxHashSet::default();
 
         // Clear nodes from worst area
         for node in worst_area_nodes.iter() {
             node2area[*node] = usize::MAX;
-            areas[worst_area].nodes.remove(node);
+            areas[worst_area].remove_node(*node, graph);
         }
 
-        for &node in worst_area_nodes.iter() {
-            let relevant_areas = graph
-                .get_neighbors(node)
-                .filter_map(|neighbor| {
-                    if node2area[neighbor] != usize::MAX {
-                        Some(node2area[neighbor])
-                    } else {
-                        None
+        let mut pending = worst_area_nodes;
+        while !pending.is_empty() {
+            let mut orphans = Vec::new();
+            for &node in pending.iter() {
+                let candidates = candidate_areas(graph, node, &node2area, options);
+                let best_area = match best_area(graph, node, candidates, &areas, &node2area) {
+                    Some(area) => area,
+                    None if options.orphan_policy == OrphanPolicy::Singleton => {
+                        new_unallocatable_area(&mut areas)
                     }
-                })
-                .filter(|&area| area != worst_area);
-
-            let mut best_area = None;
-            let mut best_tij2 = 0.0;
-
-            for area_index in relevant_areas {
-                assert_ne!(area_index, worst_area); // We can't check the worst area because we just removed it
-                assert_ne!(area_index, usize::MAX); // We have already removed all nodes from this area
+                    None => {
+                        orphans.push(node);
+                        continue;
+                    }
+                };
+                node2area[node] = best_area;
+                areas[best_area].add_node(node, graph);
+                touched_areas.insert(best_area);
+            }
 
-                let tij2 = tij2(graph, node, &areas, area_index, &node2area);
+            // Retry for as long as placing some nodes gives the others a neighbouring area.
+            // Otherwise fall back for the largest orphan that has an adjacent area, or else the
+            // largest orphan, and let the rest try to join it.
+            if orphans.len() == pending.len() {
+                let adjacent = orphans.iter().enumerate().find_map(|(position, &node)| {
+                    adjacent_area(graph, node, &areas, &node2area, options)
+                        .map(|area| (position, area))
+                });
+                let (position, area) =
+                    adjacent.unwrap_or_else(|| (0, new_unallocatable_area(&mut areas)));
+                let node = orphans.remove(position);
+                node2area[node] = area;
+                areas[area].add_node(node, graph);
+                touched_areas.insert(area);
+            }
+            pending = orphans;
+        }
 
-                if tij2 > best_tij2 {
-                    best_tij2 = tij2;
-                    best_area = Some(area_index);
-                }
+        for area_index in touched_areas {
+            if areas[area_index].unallocatable {
+                continue;
             }
-            let best_area = best_area.expect("tij2 should have been > 0");
-            node2area[node] = best_area;
-
-            areas[best_area].nodes.insert(node);
-            areas[best_area].flow_to_area += graph.nodes[node].in_degree as f64;
-            areas[best_area].flow_from_area += graph.nodes[node].out_degree as f64;
-
-            let a = graph
-                .get_edges(node, EdgeDirection::Out)
-                .filter(|&e| areas[best_area].nodes.contains(&e.target))
-                .map(|edge| edge.weight)
-                .sum::<u32>() as f64;
-
-            let b = graph
-                .get_edges(node, EdgeDirection::In)
-                .filter(|&e| areas[best_area].nodes.contains(&e.source) && e.source != e.target)
-                .map(|edge| edge.weight)
-                .sum::<u32>() as f64;
-
-            areas[best_area].self_containment += a + b;
+            queue.push_or_update(area_index, areas[area_index].x_equation(params));
+        }
+
+        if options.check_invariants {
+            drift.extend(check_invariants(graph, &areas, &node2area, iter));
         }
 
         iter += 1;
     }
-
-    // Remove empty areas before returning
-    areas
-        .iter()
-        .filter(|&a| !a.nodes.is_empty())
-        .cloned()
-        .collect::<TravelToWorkAreas>()
 }
 
-fn x_equation(area: &Area) -> f64 {
-    let size = area.flow_from_area;
-    let containment = area.self_containment;
-
-    let supply_self_containment = containment / area.flow_from_area;
-    let demand_self_containment = containment / area.flow_to_area;
-    let self_containment = supply_self_containment.min(demand_self_containment);
-
-    if size >= TARGET_SIZE && self_containment >= TARGET_CONTAINMENT {
-        1.0 / 12.0
-    } else if self_containment >= TARGET_CONTAINMENT {
-        TRADEOFF * (MIN_SIZE - size)
-    } else if size >= TARGET_SIZE {
-        self_containment - MIN_CONTAINMENT
-    } else {
-        self_containment - TRADEOFF * size - INTERCEPT
-    }
+/// The areas a node of a dissolved area may move to, in index order: those of its commuting
+/// neighbours, or of its spatial neighbours in contiguous mode
+fn candidate_areas(
+    graph: &Graph,
+    node: NodeIndex,
+    node2area: &[usize],
+    options: &Options,
+) -> Vec<usize> {
+    let neighbours = match &options.spatial_adjacency {
+        Some(adjacency) if options.contiguous => adjacency.neighbours(node).to_vec(),
+        _ => graph.get_neighbors(node).collect(),
+    };
+    // Several neighbours often share an area, which only needs scoring once
+    let mut candidates = neighbours
+        .into_iter()
+        .map(|neighbour| node2area[neighbour])
+        .filter(|&area| area != usize::MAX)
+        .collect::<Vec<usize>>();
+    candidates.sort_unstable();
+    candidates.dedup();
+    candidates
 }
 
-fn tij2(
+/// The candidate area with the highest tij2 for `node`, preferring areas that can still be
+/// dissolved over unallocatable ones. Ties go to the lowest index, and areas with no flows to or
+/// from the node are never chosen.
+fn best_area(
     graph: &Graph,
     node: NodeIndex,
-    areas: &TravelToWorkAreas,
-    area: usize,
+    candidates: Vec<usize>,
+    areas: &[Area],
     node2area: &[usize],
-) -> f64 {
-    let area_to_node = flow_area_to_node(graph, node, area, node2area);
-    let node_to_area = flow_node_to_area(graph, node, area, node2area);
-
-    let to_area = areas[area].flow_to_area;
-    let from_area = areas[area].flow_from_area;
-
-    let a = node_to_area / graph.nodes[node].out_degree as f64;
-    let b = node_to_area / to_area;
-    let c = area_to_node / from_area;
-    let d = area_to_node / graph.nodes[node].in_degree as f64;
-
-    a * b + c * d
+) -> Option<usize> {
+    let mut best: Option<(bool, f64, usize)> = None;
+    for area_index in candidates {
+        let tij2 = tij2(graph, node, areas, area_index, node2area);
+        if tij2.is_nan() || tij2 <= 0.0 {
+            continue;
+        }
+        let allocatable = !areas[area_index].unallocatable;
+        let is_better = best.is_none_or(|(best_allocatable, best_tij2, best_index)| {
+            (allocatable && !best_allocatable)
+                || (allocatable == best_allocatable
+                    && (tij2 > best_tij2 || (tij2 == best_tij2 && area_index < best_index)))
+        });
+        if is_better {
+            best = Some((allocatable, tij2, area_index));
+        }
+    }
+    best.map(|(_, _, area_index)| area_index)
 }
 
-fn flow_area_to_node(graph: &Graph, node: NodeIndex, area: usize, node2area: &[usize]) -> f64 {
-    graph
-        .get_edges(node, EdgeDirection::In)
-        .filter(|e| node2area[e.source] == area)
-        .map(|e| e.weight)
-        .sum::<u32>() as f64
+/// The spatially adjacent area with the highest tij2 for a node that no commuting neighbour
+/// could take, if the policy allows it. Unallocatable areas are never chosen.
+fn adjacent_area(
+    graph: &Graph,
+    node: NodeIndex,
+    areas: &[Area],
+    node2area: &[usize],
+    options: &Options,
+) -> Option<usize> {
+    let adjacency = match (options.orphan_policy, &options.spatial_adjacency) {
+        (OrphanPolicy::Adjacency, Some(adjacency)) => adjacency,
+        _ => return None,
+    };
+    // Adjacent areas the node does not commute to score zero, so ties go to the lowest index
+    let mut adjacent_areas = adjacency
+        .neighbours(node)
+        .iter()
+        .map(|&neighbour| node2area[neighbour])
+        .filter(|&area| area != usize::MAX && !areas[area].unallocatable)
+        .collect::<Vec<usize>>();
+    adjacent_areas.sort_unstable();
+    adjacent_areas.dedup();
+    let mut best: Option<(usize, f64)> = None;
+    for area in adjacent_areas {
+        let tij2 = tij2(graph, node, areas, area, node2area);
+        if best.is_none_or(|(_, best_tij2)| tij2 > best_tij2) {
+            best = Some((area, tij2));
+        }
+    }
+    best.map(|(area, _)| area)
 }
 
-fn flow_node_to_area(graph: &Graph, node: NodeIndex, area: usize, node2area: &[usize]) -> f64 {
-    graph
-        .get_edges(node, EdgeDirection::Out)
-        .filter(|e| node2area[e.target] == area)
-        .map(|e| e.weight)
-        .sum::<u32>() as f64
+fn new_unallocatable_area(areas: &mut Vec<Area>) -> usize {
+    areas.push(Area {
+        unallocatable: true,
+        ..Area::default()
+    });
+    areas.len() - 1
 }
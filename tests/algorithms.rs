@@ -0,0 +1,107 @@
+//! Cross-checks of the naive and v2 engines.
+//!
+//! Both engines dissolve the same worst area in each iteration and score candidate areas with
+//! tij2. They differ only in which areas a reassigned node is compared against: v2 looks at the
+//! areas of its commuting neighbours, the naive engine at every remaining area. An area with no
+//! flows to or from the node has a tij2 of zero, so the two agree whenever a node has at least one
+//! neighbour left in another area, and diverge when it does not.
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
+
+mod common;
+use common::{small_area_params, towns_with_island, FLOWS};
+
+use ttwa_detection::ttwa_naive::Naive;
+use ttwa_detection::ttwa_v2::V2;
+use ttwa_detection::{
+    read_adjacency_matrix_to_graph, Graph, Options, OrphanPolicy, Parameters, Partition,
+    RegionalisationAlgorithm, SpatialAdjacency, StopReason,
+};
+
+fn areas(partition: &Partition) -> BTreeSet<Vec<usize>> {
+    partition.sorted_nodes().into_iter().collect()
+}
+
+#[test]
+fn engines_agree_when_every_node_has_a_neighbouring_area() {
+    let (_, graph) = read_adjacency_matrix_to_graph(Path::new(FLOWS)).unwrap();
+    let params = Parameters::default();
+    let naive = Naive.run(&graph, &params, &Options::default());
+    let v2 = V2.run(&graph, &params, &Options::default());
+
+    assert_eq!(naive.iterations, v2.iterations);
+    assert_eq!(areas(&naive.partition), areas(&v2.partition));
+}
+
+#[test]
+fn naive_engine_places_isolated_nodes_in_the_first_remaining_area() {
+    let graph = towns_with_island();
+    let result = Naive.run(&graph, &small_area_params(), &Options::default());
+
+    // The island has no flows to either town, so it scores zero against both and joins the
+    // lowest-indexed one even though nobody commutes between them
+    let expected = BTreeSet::from([vec![0, 1, 2, 6, 7], vec![3, 4, 5]]);
+    assert_eq!(areas(&result.partition), expected);
+}
+
+#[test]
+fn v2_engine_keeps_isolated_nodes_as_an_unallocatable_area() {
+    let graph = towns_with_island();
+    // Once the island is dissolved its nodes have no neighbours in any other area
+    let result = V2.run(&graph, &small_area_params(), &Options::default());
+
+    let expected = BTreeSet::from([vec![0, 1, 2], vec![3, 4, 5], vec![6, 7]]);
+    assert_eq!(areas(&result.partition), expected);
+    assert!(result.partition.area_of(6).unallocatable);
+    assert!(!result.partition.area_of(0).unallocatable);
+    assert_eq!(result.stop_reason, StopReason::Converged);
+}
+
+#[test]
+fn v2_engine_places_isolated_nodes_in_a_spatially_adjacent_area() {
+    let graph = towns_with_island();
+    // The island lies next to the second town
+    let adjacency = SpatialAdjacency::from_pairs(graph.nodes.len(), [(4, 7), (3, 4)]);
+    let options = Options {
+        orphan_policy: OrphanPolicy::Adjacency,
+        spatial_adjacency: Some(Arc::new(adjacency)),
+        ..Options::default()
+    };
+    let result = V2.run(&graph, &small_area_params(), &options);
+
+    let expected = BTreeSet::from([vec![0, 1, 2], vec![3, 4, 5, 6, 7]]);
+    assert_eq!(areas(&result.partition), expected);
+    assert!(result
+        .partition
+        .areas
+        .iter()
+        .all(|area| !area.unallocatable));
+}
+
+/// Three nodes whose flows fall far short of any TTWA criterion
+fn tiny() -> Graph {
+    Graph::from_adjacency_matrix(vec![vec![10, 5, 0], vec![5, 10, 1], vec![0, 1, 10]]).unwrap()
+}
+
+#[test]
+fn naive_engine_keeps_the_last_area_when_it_fails() {
+    let result = Naive.run(&tiny(), &Parameters::default(), &Options::default());
+
+    assert_eq!(result.stop_reason, StopReason::SingleArea);
+    assert_eq!(areas(&result.partition), BTreeSet::from([vec![0, 1, 2]]));
+    assert!(result.worst_score < Parameters::default().threshold);
+    assert!(!result.partition.areas[0].unallocatable);
+}
+
+#[test]
+fn v2_engine_reports_a_failing_last_area_instead_of_converging() {
+    let result = V2.run(&tiny(), &Parameters::default(), &Options::default());
+
+    // The nodes commute with each other, so they must not end up unallocatable
+    assert_eq!(result.stop_reason, StopReason::SingleArea);
+    assert_eq!(areas(&result.partition), BTreeSet::from([vec![0, 1, 2]]));
+    assert!(result.worst_score.is_finite());
+    assert!(result.worst_score < Parameters::default().threshold);
+    assert!(!result.partition.areas[0].unallocatable);
+}
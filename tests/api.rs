@@ -0,0 +1,824 @@
+use rustc_hash::FxHashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+mod common;
+use common::{small_area_params, towns_with_zero_flow_nodes, two_towns, FLOWS};
+
+use ttwa_detection::ttwa_naive::Naive;
+use ttwa_detection::{
+    bootstrap, check_invariants, compare_partitions, eliminate_fragments, evaluate_lookup, naming,
+    read_adjacency_matrix_to_graph, read_edge_list_to_graph, read_spatial_adjacency,
+    refine_boundaries, resample_flows, sweep, travel_to_work_areas, write_area_summaries, Area,
+    EdgeListOptions, Graph, Limits, MatrixError, NonContiguousArea, Options, Parameters, Partition,
+    RegionalisationAlgorithm, SpatialAdjacency, StopReason, SweepGrid, SweepRow, SwitchedNode,
+    ZeroFlowPolicy,
+};
+
+#[test]
+fn finds_the_two_towns() {
+    let graph = two_towns();
+    let result = travel_to_work_areas(&graph, &small_area_params(), &Options::default());
+
+    assert_eq!(result.stop_reason, StopReason::Converged);
+    let partition = result.partition;
+    assert_eq!(partition.len(), 2);
+    assert_eq!(partition.sorted_nodes(), vec![vec![0, 1, 2], vec![3, 4, 5]]);
+    for node in 0..graph.nodes.len() {
+        assert!(partition.area_of(node).nodes.contains(&node));
+    }
+}
+
+#[test]
+fn converged_areas_meet_the_threshold() {
+    let (_, graph) = read_adjacency_matrix_to_graph(Path::new(FLOWS)).unwrap();
+    let params = Parameters::default();
+    let result = travel_to_work_areas(&graph, &params, &Options::default());
+
+    assert_eq!(result.stop_reason, StopReason::Converged);
+    assert!(result.worst_score >= params.threshold);
+    let areas = &result.partition.areas;
+    assert!(areas
+        .iter()
+        .all(|area| area.x_equation(&params) >= params.threshold));
+    assert_eq!(
+        areas.iter().map(|area| area.nodes.len()).sum::<usize>(),
+        graph.nodes.len()
+    );
+    assert_eq!(
+        areas.iter().map(|area| area.flow_from_area).sum::<u64>(),
+        graph
+            .out_degrees()
+            .iter()
+            .map(|&degree| degree as u64)
+            .sum::<u64>()
+    );
+}
+
+#[test]
+fn iteration_limit_stops_early() {
+    let (_, graph) = read_adjacency_matrix_to_graph(Path::new(FLOWS)).unwrap();
+    let options = Options {
+        limits: Limits {
+            max_iterations: Some(5),
+            ..Limits::default()
+        },
+        ..Options::default()
+    };
+    let result = travel_to_work_areas(&graph, &Parameters::default(), &options);
+
+    assert_eq!(result.stop_reason, StopReason::IterationLimit);
+    assert_eq!(result.iterations, 5);
+    assert_eq!(result.partition.len(), graph.nodes.len() - 5);
+}
+
+#[test]
+fn statistics_do_not_drift() {
+    let (_, graph) = read_adjacency_matrix_to_graph(Path::new(FLOWS)).unwrap();
+    let options = Options {
+        check_invariants: true,
+        ..Options::default()
+    };
+    for params in [Parameters::default(), small_area_params()] {
+        let v2 = travel_to_work_areas(&graph, &params, &options);
+        assert!(v2.iterations > 0);
+        assert!(v2.drift.is_empty(), "{:?}", v2.drift);
+        let naive = Naive.run(&graph, &params, &options);
+        assert!(naive.drift.is_empty(), "{:?}", naive.drift);
+    }
+}
+
+#[test]
+fn reports_corrupted_areas_as_drift() {
+    let graph = two_towns();
+    let mut areas = vec![
+        Area::from_nodes([0, 1, 2], &graph),
+        Area::from_nodes([3, 4, 5], &graph),
+    ];
+    let node2area = [0, 0, 0, 1, 1, 1];
+    assert!(check_invariants(&graph, &areas, &node2area, 0).is_empty());
+
+    areas[1].flow_to_area += 1;
+    areas[1].self_containment -= 1;
+    let drift = check_invariants(&graph, &areas, &node2area, 7);
+    assert_eq!(drift.len(), 1);
+    assert_eq!((drift[0].iteration, drift[0].area), (7, 1));
+    assert_eq!(
+        drift[0].found.flow_to_area,
+        drift[0].expected.flow_to_area + 1
+    );
+    assert_eq!(
+        drift[0].found.self_containment + 1,
+        drift[0].expected.self_containment
+    );
+    assert_eq!(
+        drift[0].expected.self_containment,
+        600 + 250 + 120 + 200 + 450 + 150 + 100 + 200 + 350
+    );
+
+    // A node in one area but mapped to the other
+    areas[0].nodes.remove(&2);
+    areas[1].nodes.insert(2);
+    let drift = check_invariants(&graph, &areas, &node2area, 8);
+    assert_eq!(drift.len(), 2);
+    assert_eq!(drift[0].missing_nodes, [2]);
+    assert_eq!(drift[1].unmapped_nodes, [2]);
+}
+
+#[test]
+fn rejects_invalid_parameters() {
+    assert!(Parameters::default().validate().is_ok());
+    let invalid = [
+        Parameters {
+            min_size: 30000.0,
+            ..Parameters::default()
+        },
+        Parameters {
+            min_size: -1.0,
+            ..Parameters::default()
+        },
+        Parameters {
+            min_containment: 0.8,
+            ..Parameters::default()
+        },
+        Parameters {
+            min_containment: 0.0,
+            ..Parameters::default()
+        },
+        Parameters {
+            target_containment: 1.5,
+            ..Parameters::default()
+        },
+        Parameters {
+            target_size: f64::INFINITY,
+            ..Parameters::default()
+        },
+        Parameters {
+            threshold: f64::NAN,
+            ..Parameters::default()
+        },
+    ];
+    for params in invalid {
+        assert!(params.validate().is_err(), "{:?}", params);
+    }
+}
+
+#[test]
+fn reads_parameters_from_toml_and_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let read = |name: &str, contents: &str| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        Parameters::from_file(&path)
+    };
+    // Missing fields keep their defaults
+    let expected = Parameters {
+        min_size: 1500.0,
+        threshold: 0.1,
+        ..Parameters::default()
+    };
+    assert_eq!(
+        read("params.toml", "min_size = 1500.0\nthreshold = 0.1\n").unwrap(),
+        expected
+    );
+    assert_eq!(
+        read("params.json", r#"{"min_size": 1500.0, "threshold": 0.1}"#).unwrap(),
+        expected
+    );
+
+    assert!(read("unknown.toml", "min_sise = 1500.0\n").is_err());
+    assert!(read("unknown.json", r#"{"min_sise": 1500.0}"#).is_err());
+    assert!(read("params.yaml", "min_size: 1500.0\n").is_err());
+}
+
+/// Reads a matrix written to a temporary file, expecting it to be rejected
+fn matrix_error(contents: &str) -> MatrixError {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("flows.csv");
+    std::fs::write(&path, contents).unwrap();
+    match read_adjacency_matrix_to_graph(&path) {
+        Err(error) => error,
+        Ok(_) => panic!("expected {:?} to be rejected", contents),
+    }
+}
+
+#[test]
+fn reports_malformed_matrices() {
+    match matrix_error("code,a,b\na,1,x\nb,2,3\n") {
+        MatrixError::InvalidCount {
+            line,
+            column,
+            value,
+            ..
+        } => {
+            assert_eq!(line, 2);
+            assert_eq!(column, "b");
+            assert_eq!(value, "x");
+        }
+        other => panic!("expected an invalid count error, got {:?}", other),
+    }
+    for value in ["NaN", "inf", "-inf"] {
+        let contents = format!("code,a,b\na,1,2\nb,{},4\n", value);
+        assert!(matches!(
+            matrix_error(&contents),
+            MatrixError::InvalidCount { line: 3, value: ref found, .. } if found == value
+        ));
+    }
+    assert!(matches!(
+        matrix_error("code,a,b\na,1,2\nb,-3,4\n"),
+        MatrixError::NegativeCount { line: 3, ref column, ref value, .. }
+            if column == "a" && value == "-3"
+    ));
+    assert!(matches!(
+        matrix_error("code,a,b\na,1,2.5\nb,3,4\n"),
+        MatrixError::FractionalCount { line: 2, ref column, ref value, .. }
+            if column == "b" && value == "2.5"
+    ));
+    assert!(matches!(
+        matrix_error("code,a,b\na,1,2\nb,3\n"),
+        MatrixError::RaggedRow {
+            line: 3,
+            expected: 3,
+            found: 2,
+            ..
+        }
+    ));
+    assert!(matches!(
+        matrix_error("code,a,b\na,1,2\nb,3,4\nc,5,6\n"),
+        MatrixError::NotSquare {
+            rows: 3,
+            columns: 2,
+            ..
+        }
+    ));
+    assert!(matches!(
+        matrix_error("code,a,b\na,1,2\na,3,4\n"),
+        MatrixError::DuplicateCode { line: 3, first_line: 2, ref code, .. } if code == "a"
+    ));
+    assert!(matches!(
+        matrix_error("code,a,a\na,1,2\nb,3,4\n"),
+        MatrixError::DuplicateColumn { column: 2, ref code, .. } if code == "a"
+    ));
+    assert!(matches!(
+        matrix_error("code,a,c\na,1,2\nb,3,4\n"),
+        MatrixError::CodeSetMismatch { ref only_in_rows, ref only_in_columns, .. }
+            if only_in_rows == &["b"] && only_in_columns == &["c"]
+    ));
+    assert!(matches!(
+        read_adjacency_matrix_to_graph(Path::new("tests/fixtures/missing.csv")),
+        Err(MatrixError::Csv { .. })
+    ));
+}
+
+#[test]
+fn reorders_matrix_columns_to_match_the_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("flows.csv");
+    std::fs::write(&path, "code,b,a\na,1,2\nb,3,4\n").unwrap();
+
+    let (codes, graph) = read_adjacency_matrix_to_graph(&path).unwrap();
+    assert_eq!(codes, ["a", "b"]);
+    let edges = graph
+        .edges()
+        .map(|edge| (edge.source, edge.target, edge.weight))
+        .collect::<Vec<_>>();
+    assert_eq!(edges, [(0, 0, 2), (0, 1, 1), (1, 0, 4), (1, 1, 3)]);
+}
+
+fn edge_list_options() -> EdgeListOptions {
+    EdgeListOptions {
+        origin_column: "home".to_owned(),
+        destination_column: "work".to_owned(),
+        count_column: "people".to_owned(),
+        include_unknown_origins: false,
+    }
+}
+
+#[test]
+fn reads_edge_lists() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("flows.csv");
+    // Columns in any order with an extra one, a repeated pair, counts written as "12.0" or
+    // padded with spaces, and no row for most pairs
+    std::fs::write(
+        &path,
+        "year,people,work,home
+2011,12.0,b,a
+2011,5,a,a
+2011, 3 ,b,a
+2011,7,a,b
+",
+    )
+    .unwrap();
+
+    let (codes, graph) = read_edge_list_to_graph(&path, &edge_list_options()).unwrap();
+    assert_eq!(codes, ["a", "b"]);
+    let edges = graph
+        .edges()
+        .map(|edge| (edge.source, edge.target, edge.weight))
+        .collect::<Vec<_>>();
+    assert_eq!(edges, [(0, 0, 5), (0, 1, 15), (1, 0, 7)]);
+}
+
+#[test]
+fn rejects_edge_list_origins_that_are_not_destinations() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("flows.csv");
+    std::fs::write(
+        &path,
+        "home,work,people
+a,a,5
+c,a,2
+b,b,4
+",
+    )
+    .unwrap();
+
+    let error = read_edge_list_to_graph(&path, &edge_list_options()).unwrap_err();
+    assert!(
+        error.to_string().contains("line 3: origin \"c\""),
+        "{}",
+        error
+    );
+
+    let options = EdgeListOptions {
+        include_unknown_origins: true,
+        ..edge_list_options()
+    };
+    let (codes, graph) = read_edge_list_to_graph(&path, &options).unwrap();
+    assert_eq!(codes, ["a", "b", "c"]);
+    assert_eq!(graph.nodes[2].out_degree, 2);
+    assert_eq!(graph.nodes[2].in_degree, 0);
+}
+
+#[test]
+fn reports_malformed_edge_list_counts() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("flows.csv");
+    std::fs::write(
+        &path,
+        "home,work,people
+a,a,5
+a,b,-2
+b,b,4
+",
+    )
+    .unwrap();
+
+    let error = read_edge_list_to_graph(&path, &edge_list_options()).unwrap_err();
+    match error.downcast_ref::<MatrixError>() {
+        Some(MatrixError::NegativeCount {
+            line,
+            column,
+            value,
+            ..
+        }) => {
+            assert_eq!(*line, 3);
+            assert_eq!(column, "people");
+            assert_eq!(value, "-2");
+        }
+        _ => panic!("expected a negative count error, got {}", error),
+    }
+}
+
+#[test]
+fn names_areas_after_their_largest_workplaces() {
+    // Every node's workers stay at home, so each node's jobs are the value on the diagonal
+    let jobs = [100, 79, 10, 100, 80, 100, 50];
+    let graph = Graph::from_adjacency_matrix(
+        (0..jobs.len())
+            .map(|i| {
+                (0..jobs.len())
+                    .map(|j| if i == j { jobs[i] } else { 0 })
+                    .collect()
+            })
+            .collect(),
+    )
+    .unwrap();
+    let codes = ["E1", "E2", "E3", "E4", "E5", "E6", "E7"].map(String::from);
+    let names = FxHashMap::from_iter(
+        [
+            ("E1", "Town"),
+            ("E2", "Village"),
+            ("E4", "Leeds"),
+            ("E5", "Bradford"),
+            ("E6", "Town"),
+        ]
+        .map(|(code, name)| (code.to_owned(), name.to_owned())),
+    );
+    // Given in a different order from the dominant codes E7, E6, E4 and E1
+    let partition = Partition::from_areas(
+        [vec![6], vec![2, 5], vec![3, 4], vec![0, 1]].map(|nodes| Area::from_nodes(nodes, &graph)),
+        graph.nodes.len(),
+    );
+
+    let (partition, area_names) =
+        naming::sort_and_name_areas(partition, &graph, &codes, Some(&names));
+    assert_eq!(
+        partition.sorted_nodes(),
+        vec![vec![0, 1], vec![3, 4], vec![2, 5], vec![6]]
+    );
+    assert_eq!(
+        area_names,
+        [
+            // Village has 79% of the jobs of Town, just short of a joint name
+            "Town (E1)",
+            // Bradford has exactly 80% of the jobs of Leeds
+            "Leeds & Bradford",
+            "Town (E6)",
+            // No entry in the names lookup
+            "E7",
+        ]
+    );
+}
+
+#[test]
+fn summarises_areas() {
+    let graph =
+        Graph::from_adjacency_matrix(vec![vec![10, 50, 0], vec![5, 20, 0], vec![0, 8, 7]]).unwrap();
+    let codes = ["a", "b", "c"].map(String::from);
+    let area = Area::from_nodes([0, 1], &graph);
+    let summary = area.summary(0, "b", &graph, &codes, &Parameters::default());
+
+    assert_eq!(summary.nodes, 2);
+    assert_eq!(summary.resident_workers, 85);
+    assert_eq!(summary.workplace_jobs, 93);
+    assert_eq!(summary.internal_flow, 85);
+    assert_eq!(summary.supply_self_containment, 1.0);
+    assert_eq!(summary.demand_self_containment, 85.0 / 93.0);
+    // a sends the most workers but b takes in the most
+    assert_eq!(summary.largest_origin, "a");
+    assert_eq!(summary.largest_destination, "b");
+
+    let dir = tempfile::tempdir().unwrap();
+    let csv_path = dir.path().join("summary.csv");
+    write_area_summaries(&csv_path, std::slice::from_ref(&summary)).unwrap();
+    let csv = std::fs::read_to_string(&csv_path).unwrap();
+    assert!(csv.starts_with("area,name,nodes,resident_workers,workplace_jobs,internal_flow,"));
+    assert!(csv
+        .lines()
+        .nth(1)
+        .unwrap()
+        .starts_with("0,b,2,85,93,85,1.0,"));
+
+    let json_path = dir.path().join("summary.json");
+    write_area_summaries(&json_path, &[summary]).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(json[0]["largest_origin"], "a");
+    assert_eq!(json[0]["largest_destination"], "b");
+    assert_eq!(json[0]["resident_workers"], 85);
+}
+
+#[test]
+fn attaches_zero_flow_nodes_to_their_strongest_link() {
+    let graph = towns_with_zero_flow_nodes();
+    let result = travel_to_work_areas(&graph, &small_area_params(), &Options::default());
+
+    assert_eq!(result.zero_flow_nodes, vec![6, 7]);
+    assert_eq!(result.excluded_nodes, vec![7]);
+    assert_eq!(result.stop_reason, StopReason::Converged);
+    assert!(!result.worst_score.is_nan());
+    let partition = result.partition;
+    assert_eq!(
+        partition.sorted_nodes(),
+        vec![vec![0, 1, 2], vec![3, 4, 5, 6], vec![7]]
+    );
+    assert!(partition.area_of(7).unallocatable);
+}
+
+#[test]
+fn excluded_zero_flow_nodes_are_left_out() {
+    let graph = towns_with_zero_flow_nodes();
+    let options = Options {
+        zero_flow_policy: ZeroFlowPolicy::Exclude,
+        ..Options::default()
+    };
+    let result = travel_to_work_areas(&graph, &small_area_params(), &options);
+
+    assert_eq!(result.excluded_nodes, vec![6, 7]);
+    let partition = result.partition;
+    assert_eq!(
+        partition.sorted_nodes(),
+        vec![vec![0, 1, 2], vec![3, 4, 5], vec![6], vec![7]]
+    );
+    for area in partition.areas.iter() {
+        let score = area.x_equation(&small_area_params());
+        assert!(!score.is_nan());
+    }
+}
+
+#[test]
+fn contiguous_mode_keeps_areas_contiguous() {
+    let (codes, graph) = read_adjacency_matrix_to_graph(Path::new(FLOWS)).unwrap();
+    let adjacency =
+        read_spatial_adjacency(Path::new("tests/fixtures/adjacency.csv"), &codes).unwrap();
+    let adjacency = Arc::new(adjacency);
+    let options = Options {
+        spatial_adjacency: Some(adjacency.clone()),
+        contiguous: true,
+        ..Options::default()
+    };
+    let result = travel_to_work_areas(&graph, &Parameters::default(), &options);
+
+    assert_eq!(result.stop_reason, StopReason::Converged);
+    assert_eq!(adjacency.non_contiguous_areas(&result.partition), vec![]);
+}
+
+#[test]
+fn reports_non_contiguous_areas() {
+    let graph = two_towns();
+    // The nodes lie in a row, so the second area has an exclave
+    let adjacency = SpatialAdjacency::from_pairs(6, (0..5).map(|node| (node, node + 1)));
+    let partition = Partition::from_areas(
+        [
+            Area::from_nodes([0, 1, 2, 3], &graph),
+            Area::from_nodes([4, 5], &graph),
+        ],
+        6,
+    );
+    assert_eq!(adjacency.non_contiguous_areas(&partition), vec![]);
+
+    let partition = Partition::from_areas(
+        [
+            Area::from_nodes([0, 2, 3], &graph),
+            Area::from_nodes([1, 4, 5], &graph),
+        ],
+        6,
+    );
+    let expected = vec![
+        NonContiguousArea {
+            area: 0,
+            fragments: vec![vec![2, 3], vec![0]],
+        },
+        NonContiguousArea {
+            area: 1,
+            fragments: vec![vec![4, 5], vec![1]],
+        },
+    ];
+    assert_eq!(adjacency.non_contiguous_areas(&partition), expected);
+}
+
+#[test]
+fn moves_fragments_until_areas_are_contiguous() {
+    let graph = two_towns();
+    let adjacency = SpatialAdjacency::from_pairs(6, (0..5).map(|node| (node, node + 1)));
+    let partition = Partition::from_areas(
+        [
+            Area::from_nodes([0, 2, 3], &graph),
+            Area::from_nodes([1, 4, 5], &graph),
+        ],
+        6,
+    );
+    let cleanup = eliminate_fragments(partition, &graph, &adjacency, &small_area_params());
+
+    // Node 0 is the smallest piece; once it joins node 1, nodes 4 and 5 are the detached piece
+    let moved = cleanup
+        .moves
+        .iter()
+        .map(|fragment| (fragment.nodes.clone(), fragment.from, fragment.to))
+        .collect::<Vec<_>>();
+    assert_eq!(moved, vec![(vec![0], 0, 1), (vec![4, 5], 1, 0)]);
+    assert!(cleanup.stranded.is_empty());
+    let partition = cleanup.partition;
+    assert_eq!(partition.sorted_nodes(), vec![vec![2, 3, 4, 5], vec![0, 1]]);
+    assert_eq!(adjacency.non_contiguous_areas(&partition), vec![]);
+    for area in partition.areas.iter() {
+        let recomputed = Area::from_nodes(area.nodes.iter().copied(), &graph);
+        assert_eq!(area.stats(), recomputed.stats());
+    }
+}
+
+#[test]
+fn refinement_moves_misplaced_boundary_nodes() {
+    let graph = two_towns();
+    let partition = Partition::from_areas(
+        [
+            Area::from_nodes([0, 1], &graph),
+            Area::from_nodes([2, 3, 4, 5], &graph),
+        ],
+        6,
+    );
+    let refinement = refine_boundaries(partition, &graph, &small_area_params(), None, 10);
+
+    assert_eq!(refinement.moves.len(), 1);
+    let node_move = &refinement.moves[0];
+    assert_eq!((node_move.node, node_move.from, node_move.to), (2, 1, 0));
+    assert!(node_move.to_tij2 > node_move.from_tij2);
+    assert_eq!(refinement.passes, 2);
+    assert_eq!(
+        refinement.partition.sorted_nodes(),
+        vec![vec![0, 1, 2], vec![3, 4, 5]]
+    );
+}
+
+#[test]
+fn refinement_keeps_areas_valid_and_raises_internal_flow() {
+    let (_, graph) = read_adjacency_matrix_to_graph(Path::new(FLOWS)).unwrap();
+    let params = Parameters::default();
+    let result = travel_to_work_areas(&graph, &params, &Options::default());
+    let internal_flow = |partition: &Partition| {
+        partition
+            .areas
+            .iter()
+            .map(|area| area.self_containment)
+            .sum::<u64>()
+    };
+    let before = internal_flow(&result.partition);
+
+    let refinement = refine_boundaries(result.partition, &graph, &params, None, 20);
+    let gain = refinement
+        .moves
+        .iter()
+        .map(|node_move| node_move.self_containment_gain)
+        .sum::<u64>();
+    assert_eq!(internal_flow(&refinement.partition), before + gain);
+    for area in refinement.partition.areas.iter() {
+        assert!(area.x_equation(&params) >= params.threshold);
+        let recomputed = Area::from_nodes(area.nodes.iter().copied(), &graph);
+        assert_eq!(area.stats(), recomputed.stats());
+    }
+}
+
+#[test]
+fn evaluates_an_existing_lookup_without_changing_it() {
+    let (codes, graph) = read_adjacency_matrix_to_graph(Path::new(FLOWS)).unwrap();
+    let lookup = Path::new("tests/fixtures/flows_ttwas_small.csv");
+    // The lookup was produced with these parameters, so every area meets them
+    let small = Parameters {
+        min_size: 1500.0,
+        target_size: 10000.0,
+        target_containment: 0.7,
+        min_containment: 0.6,
+        ..Parameters::default()
+    };
+    let evaluations = evaluate_lookup(lookup, &graph, &codes, &small).unwrap();
+    assert_eq!(evaluations.len(), 21);
+    assert_eq!(
+        evaluations.iter().map(|area| area.nodes).sum::<usize>(),
+        codes.len()
+    );
+    assert!(evaluations.iter().all(|area| !area.fails));
+
+    // The default parameters ask for larger areas than some of these
+    let params = Parameters::default();
+    let evaluations = evaluate_lookup(lookup, &graph, &codes, &params).unwrap();
+    assert!(evaluations.iter().any(|area| area.fails));
+    for area in evaluations.iter() {
+        assert_eq!(area.fails, area.x_equation < params.threshold);
+        assert_eq!(
+            area.below_min_size,
+            (area.resident_workers as f64) < params.min_size
+        );
+    }
+}
+
+#[test]
+fn compares_single_node_partitions() {
+    let graph = Graph::from_adjacency_matrix(vec![vec![10]]).unwrap();
+    let partition = Partition::from_areas([Area::from_nodes([0], &graph)], 1);
+    let comparison = compare_partitions(&partition, &partition, &graph);
+    assert_eq!(comparison.adjusted_rand_index, 1.0);
+    assert_eq!(comparison.normalised_mutual_information, 1.0);
+    assert_eq!(comparison.variation_of_information, 0.0);
+}
+
+#[test]
+fn compares_partitions() {
+    let graph = two_towns();
+    let towns = Partition::from_areas(
+        [
+            Area::from_nodes([0, 1, 2], &graph),
+            Area::from_nodes([3, 4, 5], &graph),
+        ],
+        6,
+    );
+    let same = compare_partitions(&towns, &towns, &graph);
+    assert_eq!(same.adjusted_rand_index, 1.0);
+    assert!((same.normalised_mutual_information - 1.0).abs() < 1e-12);
+    assert!(same.variation_of_information.abs() < 1e-12);
+    assert_eq!(same.worker_agreement, 1.0);
+    assert!(same.switched.is_empty());
+
+    // Node 2 moves to the second town
+    let moved = Partition::from_areas(
+        [
+            Area::from_nodes([0, 1], &graph),
+            Area::from_nodes([2, 3, 4, 5], &graph),
+        ],
+        6,
+    );
+    let comparison = compare_partitions(&towns, &moved, &graph);
+    // Pairs together in both: 1 + 3; expected by chance 6 * 7 / 15; mean pairs (6 + 7) / 2
+    let expected_ari = (4.0 - 2.8) / (6.5 - 2.8);
+    assert!((comparison.adjusted_rand_index - expected_ari).abs() < 1e-12);
+    assert!(comparison.variation_of_information > 0.0);
+    assert_eq!(
+        comparison.switched,
+        vec![SwitchedNode {
+            node: 2,
+            area_a: 0,
+            area_b: 1,
+            matched_b: 0,
+        }]
+    );
+    let workers = |nodes: &[usize]| {
+        nodes
+            .iter()
+            .map(|&node| graph.nodes[node].out_degree as f64)
+            .sum::<f64>()
+    };
+    let expected_agreement = 1.0 - workers(&[2]) / workers(&[0, 1, 2, 3, 4, 5]);
+    assert!((comparison.worker_agreement - expected_agreement).abs() < 1e-12);
+    assert_eq!(comparison.best_matches[1].area_b, 1);
+    // The second town is entirely within its match, which also has node 2
+    assert_eq!(
+        comparison.best_matches[1].jaccard,
+        workers(&[3, 4, 5]) / workers(&[2, 3, 4, 5])
+    );
+}
+
+#[test]
+fn sweep_matches_separate_runs() {
+    let graph = two_towns();
+    let grid = SweepGrid {
+        target_size: vec![2000.0, 4000.0],
+        min_size: vec![500.0, 1000.0],
+        ..SweepGrid::default()
+    };
+    let parameter_sets = grid.parameter_sets();
+    assert_eq!(parameter_sets.len(), 4);
+    assert_eq!(parameter_sets[1].target_size, 2000.0);
+    assert_eq!(parameter_sets[1].min_size, 1000.0);
+
+    let results = sweep(&graph, &parameter_sets, &Options::default());
+    for (params, result) in parameter_sets.iter().zip(results.iter()) {
+        let single = travel_to_work_areas(&graph, params, &Options::default());
+        assert_eq!(
+            result.partition.sorted_nodes(),
+            single.partition.sorted_nodes()
+        );
+    }
+
+    let row = SweepRow::new(0, &parameter_sets[0], &results[0]);
+    assert_eq!(row.areas, 2);
+    assert_eq!(row.failing_worker_share, 0.0);
+    assert!(row.min_self_containment <= row.median_self_containment);
+}
+
+#[test]
+fn graph_can_be_shared_across_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Graph>();
+    assert_send_sync::<Options>();
+}
+
+#[test]
+fn parallel_runs_match_serial_runs() {
+    let (_, graph) = read_adjacency_matrix_to_graph(Path::new(FLOWS)).unwrap();
+    let grid = SweepGrid {
+        min_size: vec![1500.0, 3500.0],
+        target_size: vec![10000.0, 25000.0],
+        ..SweepGrid::default()
+    };
+    let parameter_sets = grid.parameter_sets();
+    let on_threads = |threads: usize| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+    };
+
+    let serial = on_threads(1).install(|| sweep(&graph, &parameter_sets, &Options::default()));
+    let parallel = on_threads(4).install(|| sweep(&graph, &parameter_sets, &Options::default()));
+    for (serial, parallel) in serial.iter().zip(parallel.iter()) {
+        assert_eq!(
+            serial.partition.node_to_area,
+            parallel.partition.node_to_area
+        );
+        assert_eq!(serial.iterations, parallel.iterations);
+    }
+
+    let params = Parameters::default();
+    let baseline = &serial[1].partition;
+    let replicates = |threads: usize| {
+        on_threads(threads)
+            .install(|| bootstrap(&graph, baseline, &params, &Options::default(), 6, 42))
+    };
+    let serial = replicates(1);
+    let parallel = replicates(4);
+    assert_eq!(serial.len(), 6);
+    for (serial, parallel) in serial.iter().zip(parallel.iter()) {
+        assert_eq!(serial.replicate, parallel.replicate);
+        assert_eq!(
+            serial.result.partition.node_to_area,
+            parallel.result.partition.node_to_area
+        );
+        assert_eq!(serial.switched, parallel.switched);
+    }
+    // Different replicates draw different flows
+    assert_ne!(
+        resample_flows(&graph, 42, 0).edges().collect::<Vec<_>>(),
+        resample_flows(&graph, 42, 1).edges().collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,378 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+use common::FLOWS;
+
+use ttwa_detection::io::read_lookup;
+
+type Lookup = BTreeMap<String, String>;
+
+/// Runs the binary on `input` with extra arguments and returns the resulting lookup
+fn run_ttwa(input: &str, args: &[&str]) -> Lookup {
+    let dir = tempfile::tempdir().unwrap();
+    run_ttwa_to(input, &dir.path().join("ttwas.csv"), args)
+}
+
+/// Runs the binary on `input`, writing the lookup to `output`, and reads it back
+fn run_ttwa_to(input: &str, output: &Path, args: &[&str]) -> Lookup {
+    let status = Command::new(env!("CARGO_BIN_EXE_ttwa-detection"))
+        .arg("run")
+        .arg(input)
+        .arg(output)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        status.status.success(),
+        "{}",
+        String::from_utf8_lossy(&status.stderr)
+    );
+    read_lookup(output).unwrap()
+}
+
+/// Runs a subcommand, expecting it to succeed, and returns its standard output and error
+fn run_command(args: &[&str]) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_ttwa-detection"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+/// Runs a subcommand that prints its report to stdout and returns the report
+fn run_report(args: &[&str]) -> String {
+    run_command(args).0
+}
+
+/// Runs the binary expecting it to fail and returns its error output
+fn run_failure(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_ttwa-detection"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    String::from_utf8(output.stderr).unwrap()
+}
+
+/// Two lookups describe the same TTWAs if their area labels map one-to-one onto each other
+fn assert_same_partition(actual: &Lookup, expected: &Lookup) {
+    assert_eq!(actual.len(), expected.len());
+    let mut forward = HashMap::new();
+    let mut backward = HashMap::new();
+    for (code, expected_area) in expected {
+        let actual_area = &actual[code];
+        assert_eq!(
+            forward.entry(expected_area).or_insert(actual_area),
+            &actual_area,
+            "{} is not in the expected TTWA",
+            code
+        );
+        assert_eq!(
+            backward.entry(actual_area).or_insert(expected_area),
+            &expected_area,
+            "{} is not in the expected TTWA",
+            code
+        );
+    }
+}
+
+const SMALL_AREA_ARGS: [&str; 8] = [
+    "--min-size",
+    "1500",
+    "--target-size",
+    "10000",
+    "--target-containment",
+    "0.7",
+    "--min-containment",
+    "0.6",
+];
+
+// The naive engine finds the worst area by scanning every area, with the same tie-breaking as
+// the indexed heap in v2. Every node of the fixture keeps a commuting neighbour in another area,
+// so the engines only agree if the heap picks the same worst area in every iteration.
+#[test]
+fn heap_selection_matches_linear_scan() {
+    let linear_scan = run_ttwa(FLOWS, &["--algorithm", "naive"]);
+    assert_same_partition(&run_ttwa(FLOWS, &["--algorithm", "v2"]), &linear_scan);
+}
+
+#[test]
+fn heap_selection_matches_linear_scan_with_small_areas() {
+    let linear_scan = run_ttwa(
+        FLOWS,
+        &[&SMALL_AREA_ARGS[..], &["--algorithm", "naive"]].concat(),
+    );
+    let heap = run_ttwa(
+        FLOWS,
+        &[&SMALL_AREA_ARGS[..], &["--algorithm", "v2"]].concat(),
+    );
+    assert_same_partition(&heap, &linear_scan);
+}
+
+// Snapshots of the output, recorded when the reassignment order was made deterministic. That
+// change moved some nodes compared with earlier versions, so these only catch unintended changes
+// to the results and say nothing about their correctness.
+#[test]
+fn matches_the_recorded_lookup() {
+    let expected = read_lookup(Path::new("tests/fixtures/flows_ttwas.csv")).unwrap();
+    assert_same_partition(&run_ttwa(FLOWS, &[]), &expected);
+}
+
+#[test]
+fn matches_the_recorded_lookup_with_small_areas() {
+    let expected = read_lookup(Path::new("tests/fixtures/flows_ttwas_small.csv")).unwrap();
+    assert_same_partition(&run_ttwa(FLOWS, &SMALL_AREA_ARGS), &expected);
+}
+
+#[test]
+fn results_do_not_depend_on_row_or_column_order() {
+    let original = run_ttwa(FLOWS, &[]);
+    let shuffled = run_ttwa("tests/fixtures/flows_shuffled.csv", &[]);
+    assert_same_partition(&shuffled, &original);
+    assert_eq!(shuffled, run_ttwa("tests/fixtures/flows_shuffled.csv", &[]));
+}
+
+#[test]
+fn reads_and_writes_compressed_files() {
+    let expected = read_lookup(Path::new("tests/fixtures/flows_ttwas.csv")).unwrap();
+    let flows = std::fs::read(FLOWS).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    let gzip_input = dir.path().join("flows.csv.gz");
+    let mut encoder = flate2::write::GzEncoder::new(
+        std::fs::File::create(&gzip_input).unwrap(),
+        flate2::Compression::default(),
+    );
+    encoder.write_all(&flows).unwrap();
+    encoder.finish().unwrap();
+    // No extension, so the compression has to be recognised from the magic bytes
+    let zstd_input = dir.path().join("flows");
+    std::fs::write(&zstd_input, zstd::encode_all(&flows[..], 0).unwrap()).unwrap();
+
+    for (input, output) in [
+        (&gzip_input, "ttwas.csv.zst"),
+        (&zstd_input, "ttwas.csv.gz"),
+    ] {
+        let output = dir.path().join(output);
+        let lookup = run_ttwa_to(input.to_str().unwrap(), &output, &[]);
+        assert_same_partition(&lookup, &expected);
+        // The output really is compressed rather than plain CSV with a compressed name
+        assert_ne!(std::fs::read(&output).unwrap()[0], b'c');
+    }
+}
+
+#[test]
+fn reads_from_stdin_and_writes_to_stdout() {
+    let expected = read_lookup(Path::new("tests/fixtures/flows_ttwas.csv")).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_ttwa-detection"))
+        .args(["run", "-", "-"])
+        .stdin(std::fs::File::open(FLOWS).unwrap())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Progress messages go to stderr, so stdout holds nothing but the lookup
+    let dir = tempfile::tempdir().unwrap();
+    let lookup = dir.path().join("ttwas.csv");
+    std::fs::write(&lookup, &output.stdout).unwrap();
+    assert_same_partition(&read_lookup(&lookup).unwrap(), &expected);
+}
+
+#[test]
+fn parameter_flags_override_the_config_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("params.toml");
+    // A minimum size above the default target size is rejected on its own
+    std::fs::write(&config, "min_size = 30000.0\n").unwrap();
+    let config = config.to_str().unwrap();
+    let stderr = run_failure(&["run", FLOWS, "--config", config]);
+    assert!(
+        stderr.contains("min_size (30000) must be smaller"),
+        "{}",
+        stderr
+    );
+
+    let expected = read_lookup(Path::new("tests/fixtures/flows_ttwas.csv")).unwrap();
+    let lookup = run_ttwa(FLOWS, &["--config", config, "--min-size", "3500"]);
+    assert_same_partition(&lookup, &expected);
+}
+
+#[test]
+fn rejects_bad_arguments_before_reading_the_flows() {
+    // The flows file does not exist, so only errors found before reading it can be reported
+    for (args, message) in [
+        (&["--timeout=-1"][..], "--timeout must be"),
+        (&["--orphans", "adjacency"], "--orphans adjacency needs"),
+        (&["--contiguous"], "--contiguous needs"),
+        (&["--fix-fragments"], "--fix-fragments needs"),
+        (
+            &[
+                "--contiguous",
+                "--adjacency",
+                "adjacency.csv",
+                "--algorithm",
+                "naive",
+            ],
+            "--contiguous is only supported",
+        ),
+        (
+            &["--orphans", "singleton", "--algorithm", "naive"],
+            "--orphans is only supported",
+        ),
+    ] {
+        let stderr = run_failure(&[&["run", "missing.csv"][..], args].concat());
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+}
+
+#[test]
+fn fail_unconverged_sets_the_exit_status() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("ttwas.csv");
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_ttwa-detection"))
+            .args(["run", FLOWS, output.to_str().unwrap()])
+            .args(extra)
+            .arg("--fail-unconverged")
+            .output()
+            .unwrap()
+    };
+
+    let stopped = run(&["5"]);
+    assert_eq!(stopped.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&stopped.stderr).contains("iteration limit reached"));
+    // The areas found so far are still written
+    assert_eq!(read_lookup(&output).unwrap().len(), 144);
+
+    assert_eq!(run(&[]).status.code(), Some(0));
+}
+
+#[test]
+fn inspect_summarises_the_flows() {
+    let report = run_report(&["inspect", FLOWS, "--json"]);
+    let summary: serde_json::Value = serde_json::from_str(&report).unwrap();
+    assert_eq!(summary["nodes"], 144);
+    assert_eq!(summary["zero_rows"], 0);
+    let share = summary["self_flow_share"].as_f64().unwrap();
+    assert!(share > 0.0 && share < 1.0);
+}
+
+#[test]
+fn evaluate_scores_a_lookup() {
+    let (_, report) = run_command(&["evaluate", FLOWS, "tests/fixtures/flows_ttwas.csv"]);
+    assert!(report.ends_with("17 of 17 areas meet the x-equation threshold\n"));
+}
+
+#[test]
+fn evaluate_writes_only_the_table_to_stdout() {
+    let (table, _) = run_command(&[
+        "evaluate",
+        FLOWS,
+        "tests/fixtures/flows_ttwas.csv",
+        "--report",
+        "-",
+    ]);
+    let mut reader = csv::Reader::from_reader(table.as_bytes());
+    assert_eq!(&reader.headers().unwrap()[0], "area");
+    let records = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(records.len(), 17);
+}
+
+#[test]
+fn compare_finds_identical_lookups() {
+    let lookup = "tests/fixtures/flows_ttwas.csv";
+    let (_, report) = run_command(&["compare", FLOWS, lookup, lookup]);
+    assert!(report.contains("Adjusted Rand index: 1.0000"));
+    assert!(report.contains("Variation of information: 0.0000"));
+    assert!(report.contains("0 of 144 nodes switched area"));
+}
+
+#[test]
+fn compare_writes_only_the_table_to_stdout() {
+    let lookup = "tests/fixtures/flows_ttwas.csv";
+    let (table, _) = run_command(&["compare", FLOWS, lookup, lookup, "--matches", "-"]);
+    let mut reader = csv::Reader::from_reader(table.as_bytes());
+    assert_eq!(&reader.headers().unwrap()[0], "area_a");
+    let records = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(records.len(), 17);
+    assert!(records.iter().all(|record| record[0] == record[1]));
+
+    // No node switched, so the table is empty apart from its header
+    let (table, _) = run_command(&["compare", FLOWS, lookup, lookup, "--switched", "-"]);
+    assert!(!table.contains("Adjusted Rand index"));
+    assert!(table.lines().count() <= 1);
+}
+
+#[test]
+fn sweep_writes_a_row_and_a_lookup_per_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let grid = dir.path().join("grid.toml");
+    // The second minimum size is above the target size, so that run is skipped
+    std::fs::write(
+        &grid,
+        "min_size = [3500.0, 30000.0]\nthreshold = [0.0, 0.1]\n",
+    )
+    .unwrap();
+    let out_dir = dir.path().join("sweep");
+    run_report(&[
+        "sweep",
+        FLOWS,
+        grid.to_str().unwrap(),
+        out_dir.to_str().unwrap(),
+    ]);
+
+    let mut reader = csv::Reader::from_path(out_dir.join("results.csv")).unwrap();
+    let runs = reader
+        .deserialize::<HashMap<String, String>>()
+        .map(|row| row.unwrap()["run"].clone())
+        .collect::<Vec<String>>();
+    assert_eq!(runs, ["0", "1"]);
+    let expected = read_lookup(Path::new("tests/fixtures/flows_ttwas.csv")).unwrap();
+    assert_same_partition(
+        &read_lookup(&out_dir.join("partitions/run_000.csv")).unwrap(),
+        &expected,
+    );
+    assert!(out_dir.join("partitions/run_001.csv").exists());
+    assert!(!out_dir.join("partitions/run_002.csv").exists());
+}
+
+#[test]
+fn bootstrap_results_do_not_depend_on_the_thread_count() {
+    let dir = tempfile::tempdir().unwrap();
+    let run = |threads: &str| {
+        let out_dir = dir.path().join(threads);
+        run_report(&[
+            "bootstrap",
+            FLOWS,
+            out_dir.to_str().unwrap(),
+            "--replicates",
+            "4",
+            "--seed",
+            "7",
+            "--threads",
+            threads,
+        ]);
+        let read = |name: &str| std::fs::read_to_string(out_dir.join(name)).unwrap();
+        (read("replicates.csv"), read("stability.csv"))
+    };
+
+    let (replicates, stability) = run("1");
+    assert_eq!(replicates.lines().count(), 5);
+    assert_eq!(stability.lines().count(), 145);
+    assert_eq!(run("3"), (replicates, stability));
+}
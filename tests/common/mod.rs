@@ -0,0 +1,62 @@
+//! Graphs and parameters shared by the integration tests.
+// Each test crate compiles its own copy of this module and uses only some of the helpers
+#![allow(dead_code)]
+
+use ttwa_detection::{Graph, Parameters};
+
+/// The 144-node flow matrix the recorded lookups in `tests/fixtures` were computed from
+pub const FLOWS: &str = "tests/fixtures/flows.csv";
+
+/// Two self-contained towns of three nodes each, joined by a little cross-commuting
+pub fn two_towns() -> Graph {
+    Graph::from_adjacency_matrix(vec![
+        vec![500, 300, 100, 5, 0, 0],
+        vec![200, 400, 150, 0, 0, 5],
+        vec![250, 100, 300, 0, 10, 0],
+        vec![0, 5, 0, 600, 250, 120],
+        vec![0, 0, 10, 200, 450, 150],
+        vec![5, 0, 0, 100, 200, 350],
+    ])
+    .unwrap()
+}
+
+/// The two towns plus two nodes (6 and 7) that only commute between themselves and are too small
+/// to form a TTWA on their own
+pub fn towns_with_island() -> Graph {
+    Graph::from_adjacency_matrix(vec![
+        vec![500, 300, 100, 5, 0, 0, 0, 0],
+        vec![200, 400, 150, 0, 0, 5, 0, 0],
+        vec![250, 100, 300, 0, 10, 0, 0, 0],
+        vec![0, 5, 0, 600, 250, 120, 0, 0],
+        vec![0, 0, 10, 200, 450, 150, 0, 0],
+        vec![5, 0, 0, 100, 200, 350, 0, 0],
+        vec![0, 0, 0, 0, 0, 0, 50, 30],
+        vec![0, 0, 0, 0, 0, 0, 20, 40],
+    ])
+    .unwrap()
+}
+
+/// The two towns plus an industrial estate (node 6) with jobs but no residents, drawing most of
+/// its workers from the second town, and an empty node (node 7)
+pub fn towns_with_zero_flow_nodes() -> Graph {
+    Graph::from_adjacency_matrix(vec![
+        vec![500, 300, 100, 5, 0, 0, 10, 0],
+        vec![200, 400, 150, 0, 0, 5, 0, 0],
+        vec![250, 100, 300, 0, 10, 0, 0, 0],
+        vec![0, 5, 0, 600, 250, 120, 40, 0],
+        vec![0, 0, 10, 200, 450, 150, 60, 0],
+        vec![5, 0, 0, 100, 200, 350, 0, 0],
+        vec![0, 0, 0, 0, 0, 0, 0, 0],
+        vec![0, 0, 0, 0, 0, 0, 0, 0],
+    ])
+    .unwrap()
+}
+
+/// Criteria small enough for each of the two towns to be a TTWA
+pub fn small_area_params() -> Parameters {
+    Parameters {
+        target_size: 2000.0,
+        min_size: 500.0,
+        ..Parameters::default()
+    }
+}